@@ -0,0 +1,111 @@
+//! Formats Clausewitz script files in place.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Parser, ValueEnum};
+use clausewitz_viewer::cst::parse_syntax_tree;
+use clausewitz_viewer::file::{read_file, write_file};
+use clausewitz_viewer::format::{
+    format_syntax_tree, BraceStyle, CommentPlacement, FormatOptions, Indent,
+};
+use clausewitz_viewer::game::find_txt_files;
+
+#[derive(Parser)]
+#[command(name = "clausefmt", about = "Formats Clausewitz script files")]
+struct Args {
+    /// Files to format. Folders are searched for `.txt` files.
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+    /// List files that are not formatted instead of formatting them.
+    #[arg(long)]
+    check: bool,
+    /// Indent with this many spaces instead of tabs.
+    #[arg(long, value_name = "N")]
+    spaces: Option<usize>,
+    #[arg(long, value_enum, default_value_t = Brace::SameLine)]
+    brace_style: Brace,
+    /// Keep blocks of plain values on one line while it is at most this wide.
+    #[arg(long, value_name = "WIDTH", default_value_t = 80)]
+    fold_width: usize,
+    /// Move comments after a pair onto their own line above it.
+    #[arg(long)]
+    comments_own_line: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Brace {
+    SameLine,
+    NextLine,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let options = FormatOptions {
+        indent: args.spaces.map_or(Indent::Tabs, Indent::Spaces),
+        brace_style: match args.brace_style {
+            Brace::SameLine => BraceStyle::SameLine,
+            Brace::NextLine => BraceStyle::NextLine,
+        },
+        fold_width: args.fold_width,
+        comments: if args.comments_own_line {
+            CommentPlacement::OwnLine
+        } else {
+            CommentPlacement::Preserve
+        },
+    };
+
+    let mut failed = false;
+
+    for path in &args.paths {
+        let files = if path.is_dir() {
+            let (files, errors) = find_txt_files(path);
+            for (folder, e) in errors {
+                eprintln!("{}: {}", folder.display(), e);
+                failed = true;
+            }
+            files
+        } else {
+            vec![path.clone()]
+        };
+
+        for file in files {
+            let source = match read_file(&file) {
+                Ok(source) => source,
+                Err(e) => {
+                    eprintln!("{}: {}", file.display(), e);
+                    failed = true;
+                    continue;
+                }
+            };
+            let tree = match parse_syntax_tree(&source.text) {
+                Ok(tree) => tree,
+                Err(e) => {
+                    eprintln!("{}: {}", file.display(), e);
+                    failed = true;
+                    continue;
+                }
+            };
+
+            let formatted = format_syntax_tree(&tree, &options);
+            if formatted == source.text {
+                continue;
+            }
+
+            if args.check {
+                println!("{}", file.display());
+                failed = true;
+            } else if let Err(e) = write_file(&file, &formatted, source.encoding) {
+                eprintln!("{}: {}", file.display(), e);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,604 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use serde_json::{json, Value};
+
+use clausewitz_viewer::export::{serialize, to_json, ExportFormat};
+use clausewitz_viewer::format::{format_pairs, FormatOptions};
+use clausewitz_viewer::game::{parse_file, parse_layers, ParsedFile};
+use clausewitz_viewer::import::import;
+use clausewitz_viewer::mods::Layer;
+use clausewitz_viewer::parser::{ConfigPair, ConfigValue, Diagnostic, Severity};
+use clausewitz_viewer::profile::{FileCategory, Profile};
+use clausewitz_viewer::query::Query;
+
+#[derive(Parser)]
+#[command(
+    name = "clausewitz-viewer",
+    about = "Viewer for Clausewitz based games",
+    long_about = "Viewer for Clausewitz based games. Opens the viewer when run without a command."
+)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub load: LoadOptions,
+}
+
+/// How to load a game folder.
+#[derive(clap::Args, Default)]
+pub struct LoadOptions {
+    /// Load this mod on top of the game folder, from its folder or `.mod` file. Can be
+    /// given several times, in load order.
+    #[arg(long = "mod", global = true, value_name = "MOD")]
+    pub mods: Vec<PathBuf>,
+    /// Also load files with this extension, as script or as the given category, such as
+    /// `lua` or `shader=graphics`. Localisation is always loaded from its own folders, so
+    /// it is not a category here. Can be given several times.
+    #[arg(
+        long = "extension",
+        global = true,
+        value_name = "EXT[=CATEGORY]",
+        value_parser = parse_extension
+    )]
+    pub extensions: Vec<(String, FileCategory)>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(flatten)]
+    Game(GameCommand),
+    /// Convert a JSON document that follows the export mapping to script.
+    Import {
+        /// JSON file to convert.
+        input: PathBuf,
+        /// Write to this file instead of standard output.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+/// The commands that load a game folder or file.
+#[derive(Subcommand)]
+pub enum GameCommand {
+    /// Parse every file and list how each one went.
+    Parse {
+        /// Game or mod folder, or a single file.
+        path: PathBuf,
+        /// Print JSON instead of text.
+        #[arg(long)]
+        json: bool,
+    },
+    /// List every parse error and warning.
+    Check {
+        /// Game or mod folder, or a single file.
+        path: PathBuf,
+        /// Print JSON instead of text.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the parsed contents as formatted script.
+    Dump {
+        /// Game or mod folder, or a single file.
+        path: PathBuf,
+        /// Only dump the file with this path, relative to the folder.
+        #[arg(long)]
+        file: Option<String>,
+    },
+    /// Export the parsed contents as JSON, YAML or TOML.
+    ///
+    /// A folder is exported as one object with an entry per file.
+    Export {
+        /// Game or mod folder, or a single file.
+        path: PathBuf,
+        /// One of `json`, `yaml` or `toml`.
+        #[arg(long, default_value = "json", value_parser = parse_format)]
+        format: ExportFormat,
+        /// Only export the file with this path, relative to the folder.
+        #[arg(long)]
+        file: Option<String>,
+        /// Write to this file instead of standard output.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Print the pairs selected by a path query, such as `technologies/*/allow/tag`.
+    Query {
+        /// Game or mod folder, or a single file.
+        path: PathBuf,
+        /// Query to run. Steps are separated by `/`; `*` matches any key, `**` any depth,
+        /// and `[key = value]` keeps pairs whose block has that value.
+        query: String,
+        /// Only query the file with this path, relative to the folder.
+        #[arg(long)]
+        file: Option<String>,
+        /// Print JSON instead of text.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print totals over all files.
+    Stats {
+        /// Game or mod folder, or a single file.
+        path: PathBuf,
+        /// Print JSON instead of text.
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Runs a command. Exits with failure when any file has parse errors or cannot be read.
+pub fn run(command: Command, options: &LoadOptions) -> ExitCode {
+    match command {
+        Command::Game(command) => run_game(command, options),
+        Command::Import { input, output } => match import_json(&input, output.as_ref()) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("{}", e);
+                ExitCode::FAILURE
+            }
+        },
+    }
+}
+
+fn run_game(command: GameCommand, options: &LoadOptions) -> ExitCode {
+    let path = match &command {
+        GameCommand::Parse { path, .. }
+        | GameCommand::Check { path, .. }
+        | GameCommand::Dump { path, .. }
+        | GameCommand::Export { path, .. }
+        | GameCommand::Query { path, .. }
+        | GameCommand::Stats { path, .. } => path,
+    };
+    let (files, unreadable) = match load(path, options) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match command {
+        GameCommand::Parse { json, .. } => parse(&files, json),
+        GameCommand::Check { json, .. } => check(&files, json),
+        GameCommand::Dump { file, .. } => dump(&files, file.as_deref()),
+        GameCommand::Export {
+            format,
+            file,
+            output,
+            ..
+        } => {
+            if let Err(e) = export(&files, format, file.as_deref(), output.as_ref()) {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+        GameCommand::Query {
+            query, file, json, ..
+        } => {
+            if let Err(e) = query_files(&files, &query, file.as_deref(), json) {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+        GameCommand::Stats { json, .. } => stats(&files, json),
+    }
+
+    let has_errors = files.iter().any(|(_, file)| {
+        file.diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error)
+    });
+
+    if has_errors || unreadable > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Parses `path` with the mods of `options` on top, sorted by relative path so output is
+/// stable between runs. Files and folders that cannot be read are reported on standard
+/// error and counted.
+fn load(
+    path: &PathBuf,
+    options: &LoadOptions,
+) -> Result<(Vec<(String, ParsedFile)>, usize), String> {
+    let mut unreadable = 0;
+    let mut files: Vec<(String, ParsedFile)> = if path.is_dir() {
+        let mut profile = Profile::detect(path);
+        for (extension, category) in &options.extensions {
+            profile.extensions.insert(extension, *category);
+        }
+
+        let mut layers = vec![Layer::game(&profile.script_root(path))];
+        for path in &options.mods {
+            layers.push(Layer::from_mod(path).map_err(|e| format!("{}: {}", path.display(), e))?);
+        }
+
+        let game = parse_layers(&layers, &profile);
+        for file in &game.report.files {
+            if let Some(error) = &file.error {
+                eprintln!("{}: {}", file.path, error);
+                unreadable += 1;
+            }
+        }
+        game.files.into_iter().collect()
+    } else if !options.mods.is_empty() {
+        return Err("mods can only be loaded on top of a game folder".to_owned());
+    } else {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let file = parse_file(path, &Profile::default())
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        vec![(name.into_owned(), file)]
+    };
+
+    files.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok((files, unreadable))
+}
+
+fn count(file: &ParsedFile, severity: Severity) -> usize {
+    file.diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == severity)
+        .count()
+}
+
+fn parse(files: &[(String, ParsedFile)], json: bool) {
+    if json {
+        let files: Vec<Value> = files
+            .iter()
+            .map(|(name, file)| {
+                json!({
+                    "file": name,
+                    "encoding": file.encoding.to_string(),
+                    "pairs": file.pairs.len(),
+                    "errors": count(file, Severity::Error),
+                    "warnings": count(file, Severity::Warning),
+                })
+            })
+            .collect();
+        println!("{}", Value::Array(files));
+        return;
+    }
+
+    for (name, file) in files {
+        println!(
+            "{}: {} pairs, {} errors, {} warnings",
+            name,
+            file.pairs.len(),
+            count(file, Severity::Error),
+            count(file, Severity::Warning)
+        );
+    }
+}
+
+fn check(files: &[(String, ParsedFile)], json: bool) {
+    let diagnostics = files
+        .iter()
+        .flat_map(|(name, file)| file.diagnostics.iter().map(move |d| (name, d)));
+
+    if json {
+        let diagnostics: Vec<Value> = diagnostics
+            .map(|(name, diagnostic)| diagnostic_json(name, diagnostic))
+            .collect();
+        println!("{}", Value::Array(diagnostics));
+        return;
+    }
+
+    for (name, diagnostic) in diagnostics {
+        println!("{}:{}", name, diagnostic);
+    }
+}
+
+fn diagnostic_json(file: &str, diagnostic: &Diagnostic) -> Value {
+    json!({
+        "file": file,
+        "line": diagnostic.span.line,
+        "column": diagnostic.span.column,
+        "severity": diagnostic.severity.to_string(),
+        "message": diagnostic.message,
+    })
+}
+
+fn dump(files: &[(String, ParsedFile)], only: Option<&str>) {
+    let options = FormatOptions::default();
+
+    for (name, file) in files {
+        if only.is_some_and(|only| only != name) {
+            continue;
+        }
+
+        if only.is_none() {
+            println!("# {}", name);
+        }
+        print!("{}", format_pairs(&file.pairs, &options));
+    }
+}
+
+fn parse_extension(extension: &str) -> Result<(String, FileCategory), String> {
+    let Some((extension, name)) = extension.split_once('=') else {
+        return Ok((extension.to_owned(), FileCategory::Script));
+    };
+
+    // The files are parsed as script whatever their category, which localisation is not.
+    let category = FileCategory::ALL
+        .into_iter()
+        .filter(|category| *category != FileCategory::Localisation)
+        .find(|category| category.to_string().eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("unknown category `{}`", name))?;
+    Ok((extension.to_owned(), category))
+}
+
+fn parse_format(format: &str) -> Result<ExportFormat, String> {
+    ExportFormat::from_extension(format).ok_or_else(|| format!("unknown format `{}`", format))
+}
+
+fn export(
+    files: &[(String, ParsedFile)],
+    format: ExportFormat,
+    only: Option<&str>,
+    output: Option<&PathBuf>,
+) -> Result<(), String> {
+    let value = match only {
+        Some(only) => {
+            let (_, file) = files
+                .iter()
+                .find(|(name, _)| name == only)
+                .ok_or_else(|| format!("no file `{}`", only))?;
+            to_json(&file.pairs)
+        }
+        None => Value::Object(
+            files
+                .iter()
+                .map(|(name, file)| (name.clone(), to_json(&file.pairs)))
+                .collect(),
+        ),
+    };
+
+    let exported = serialize(&value, format).map_err(|e| e.to_string())?;
+    write_output(&exported, output)
+}
+
+fn import_json(input: &PathBuf, output: Option<&PathBuf>) -> Result<(), String> {
+    let json = fs::read_to_string(input).map_err(|e| format!("{}: {}", input.display(), e))?;
+    let script = import(&json, &FormatOptions::default())
+        .map_err(|e| format!("{}: {}", input.display(), e))?;
+
+    write_output(&script, output)
+}
+
+fn write_output(text: &str, output: Option<&PathBuf>) -> Result<(), String> {
+    match output {
+        Some(output) => fs::write(output, text).map_err(|e| format!("{}: {}", output.display(), e)),
+        None => {
+            print!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+fn query_files(
+    files: &[(String, ParsedFile)],
+    query: &str,
+    only: Option<&str>,
+    json: bool,
+) -> Result<(), String> {
+    let query = Query::parse(query).map_err(|e| e.to_string())?;
+
+    let matches = files
+        .iter()
+        .filter(|(name, _)| only.is_none_or(|only| only == name))
+        .flat_map(|(name, file)| {
+            query
+                .select(&file.pairs)
+                .into_iter()
+                .map(move |found| (name, found))
+        });
+
+    if json {
+        let matches: Vec<Value> = matches
+            .map(|(name, found)| {
+                json!({
+                    "file": name,
+                    "line": found.pair.span.line,
+                    "path": found.path,
+                    "sign": found.pair.sign,
+                    "value": to_json(std::slice::from_ref(found.pair))[&found.pair.identifier],
+                })
+            })
+            .collect();
+        println!("{}", Value::Array(matches));
+        return Ok(());
+    }
+
+    for (name, found) in matches {
+        println!(
+            "{}:{}: {} {} {}",
+            name,
+            found.pair.span.line,
+            found.path.join("/"),
+            found.pair.sign,
+            found.pair.value
+        );
+    }
+
+    Ok(())
+}
+
+fn stats(files: &[(String, ParsedFile)], json: bool) {
+    fn count_pairs(pairs: &[ConfigPair]) -> usize {
+        pairs
+            .iter()
+            .map(|pair| match &pair.value {
+                ConfigValue::Object(children) => 1 + count_pairs(children),
+                _ => 1,
+            })
+            .sum()
+    }
+
+    let parsed = files.iter().map(|(_, file)| file);
+    let stats = json!({
+        "files": files.len(),
+        "files_with_errors": parsed.clone().filter(|file| count(file, Severity::Error) > 0).count(),
+        "top_level_pairs": parsed.clone().map(|file| file.pairs.len()).sum::<usize>(),
+        "pairs": parsed.clone().map(|file| count_pairs(&file.pairs)).sum::<usize>(),
+        "errors": parsed.clone().map(|file| count(file, Severity::Error)).sum::<usize>(),
+        "warnings": parsed.map(|file| count(file, Severity::Warning)).sum::<usize>(),
+    });
+
+    if json {
+        println!("{}", stats);
+        return;
+    }
+
+    for (key, value) in stats.as_object().unwrap() {
+        println!("{}: {}", key, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::path::Path;
+
+    /// A game folder with a valid file and, with `broken` set, a file with an error.
+    fn game(dir: &TempDir, broken: bool) -> PathBuf {
+        let root = dir.path().join("game");
+        fs::create_dir_all(root.join("common")).unwrap();
+        fs::write(root.join("common/a.txt"), "a = { b = 1 }\n").unwrap();
+        if broken {
+            fs::write(root.join("common/b.txt"), "b = = 1\n").unwrap();
+        }
+        root
+    }
+
+    fn run_args(args: &[&str]) -> ExitCode {
+        let args = Args::try_parse_from(["clausewitz-viewer"].iter().chain(args)).unwrap();
+        run(args.command.unwrap(), &args.load)
+    }
+
+    fn path(path: &Path) -> &str {
+        path.to_str().unwrap()
+    }
+
+    #[test]
+    fn test_parse_extension() {
+        assert_eq!(
+            parse_extension("yml"),
+            Ok(("yml".to_owned(), FileCategory::Script))
+        );
+        assert_eq!(
+            parse_extension("shader=graphics"),
+            Ok(("shader".to_owned(), FileCategory::Graphics))
+        );
+        assert_eq!(
+            parse_extension("dds=Interface"),
+            Ok(("dds".to_owned(), FileCategory::Interface))
+        );
+        assert!(parse_extension("shader=shaders").is_err());
+        assert!(parse_extension("yml=localisation").is_err());
+        assert!(
+            Args::try_parse_from(["clausewitz-viewer", "--extension", "a=b", "parse", "."])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = TempDir::new("cli-load");
+        let root = game(&dir, false);
+        fs::write(root.join("common/c.shader"), "c = 1\n").unwrap();
+        let mod_root = dir.path().join("mod");
+        fs::create_dir_all(mod_root.join("common")).unwrap();
+        fs::write(mod_root.join("common/a.txt"), "a = 2\n").unwrap();
+        fs::write(mod_root.join("common/d.txt"), "d = 2\n").unwrap();
+
+        let options = LoadOptions {
+            mods: vec![mod_root.clone()],
+            extensions: vec![("shader".to_owned(), FileCategory::Script)],
+        };
+        let (files, unreadable) = load(&root, &options).unwrap();
+        let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["common/a.txt", "common/c.shader", "common/d.txt"]);
+        assert_eq!(files[0].1.layer, 1);
+        assert_eq!(unreadable, 0);
+
+        let file = root.join("common/a.txt");
+        let (files, _) = load(&file, &LoadOptions::default()).unwrap();
+        assert_eq!(files[0].0, "a.txt");
+        assert!(load(&file, &options).is_err());
+        assert!(load(&dir.path().join("missing.txt"), &LoadOptions::default()).is_err());
+
+        let missing_mod = LoadOptions {
+            mods: vec![dir.path().join("missing.mod")],
+            ..LoadOptions::default()
+        };
+        assert!(load(&root, &missing_mod).is_err());
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        let dir = TempDir::new("cli-exit-codes");
+        let valid = game(&dir, false);
+        let broken_dir = TempDir::new("cli-exit-codes-broken");
+        let broken = game(&broken_dir, true);
+        let (valid, broken) = (path(&valid), path(&broken));
+
+        for command in ["parse", "check", "stats"] {
+            assert_eq!(
+                run_args(&[command, valid]),
+                ExitCode::SUCCESS,
+                "{}",
+                command
+            );
+            assert_eq!(run_args(&[command, valid, "--json"]), ExitCode::SUCCESS);
+            assert_eq!(
+                run_args(&[command, broken]),
+                ExitCode::FAILURE,
+                "{}",
+                command
+            );
+        }
+        assert_eq!(run_args(&["parse", "missing-folder"]), ExitCode::FAILURE);
+
+        assert_eq!(run_args(&["dump", valid]), ExitCode::SUCCESS);
+        assert_eq!(run_args(&["dump", broken]), ExitCode::FAILURE);
+
+        assert_eq!(run_args(&["query", valid, "a/b"]), ExitCode::SUCCESS);
+        assert_eq!(
+            run_args(&["query", valid, "a/b", "--json"]),
+            ExitCode::SUCCESS
+        );
+        assert_eq!(run_args(&["query", valid, "a/[b"]), ExitCode::FAILURE);
+
+        let exported = dir.path().join("export.json");
+        let imported = dir.path().join("import.txt");
+        assert_eq!(
+            run_args(&[
+                "export",
+                valid,
+                "--file",
+                "common/a.txt",
+                "-o",
+                path(&exported)
+            ]),
+            ExitCode::SUCCESS
+        );
+        assert_eq!(
+            run_args(&["export", valid, "--file", "common/missing.txt"]),
+            ExitCode::FAILURE
+        );
+
+        assert_eq!(
+            run_args(&["import", path(&exported), "-o", path(&imported)]),
+            ExitCode::SUCCESS
+        );
+        assert_eq!(
+            fs::read_to_string(&imported).unwrap(),
+            "a = {\n\tb = 1\n}\n"
+        );
+        assert_eq!(run_args(&["import", path(&imported)]), ExitCode::FAILURE);
+    }
+}
@@ -0,0 +1,324 @@
+//! A lossless concrete syntax tree for Clausewitz script.
+//!
+//! Unlike [`ConfigValue`](crate::parser::ConfigValue), this tree keeps whitespace, comments
+//! and the exact spelling of every key and value, so printing it with [`Display`] gives
+//! back the input byte for byte.
+
+use std::fmt::{self, Display, Formatter};
+
+use pest::error::Error;
+use pest::iterators::{Pair, Pairs};
+use pest::Parser;
+use pest_derive::Parser;
+
+use crate::parser::{ConfigElement, ConfigPair, ConfigValue};
+
+#[derive(Parser)]
+#[grammar = "cst.pest"]
+struct CstParser;
+
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct SyntaxTree {
+    pub nodes: Vec<SyntaxNode>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum SyntaxNode {
+    /// Spaces, tabs and line breaks.
+    Whitespace(String),
+    /// A comment, from the `#` up to but not including the line break.
+    Comment(String),
+    Pair(SyntaxPair),
+    /// A value without a key, as found in arrays.
+    Value(SyntaxValue),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SyntaxPair {
+    /// The key as written, including quotes if it has them.
+    pub key: String,
+    /// Whitespace and comments between the key and the sign.
+    pub before_sign: Vec<SyntaxNode>,
+    pub sign: String,
+    /// Whitespace and comments between the sign and the value.
+    pub before_value: Vec<SyntaxNode>,
+    pub value: SyntaxValue,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum SyntaxValue {
+    /// A string, number, date or identifier, exactly as written.
+    Scalar(String),
+    /// The contents of a `{ ... }` block: pairs, values and the trivia between them.
+    Block(Vec<SyntaxNode>),
+    /// A block prefixed with a name, such as `rgb { 255 0 0 }`.
+    Named {
+        name: String,
+        before_block: Vec<SyntaxNode>,
+        block: Vec<SyntaxNode>,
+    },
+}
+
+impl Display for SyntaxTree {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write_nodes(f, &self.nodes)
+    }
+}
+
+impl Display for SyntaxNode {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            SyntaxNode::Whitespace(text) | SyntaxNode::Comment(text) => write!(f, "{}", text),
+            SyntaxNode::Pair(pair) => write!(f, "{}", pair),
+            SyntaxNode::Value(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl Display for SyntaxPair {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.key)?;
+        write_nodes(f, &self.before_sign)?;
+        write!(f, "{}", self.sign)?;
+        write_nodes(f, &self.before_value)?;
+        write!(f, "{}", self.value)
+    }
+}
+
+impl Display for SyntaxValue {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            SyntaxValue::Scalar(text) => write!(f, "{}", text),
+            SyntaxValue::Block(nodes) => {
+                write!(f, "{{")?;
+                write_nodes(f, nodes)?;
+                write!(f, "}}")
+            }
+            SyntaxValue::Named {
+                name,
+                before_block,
+                block,
+            } => {
+                write!(f, "{}", name)?;
+                write_nodes(f, before_block)?;
+                write!(f, "{{")?;
+                write_nodes(f, block)?;
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+impl From<&ConfigPair> for SyntaxPair {
+    fn from(pair: &ConfigPair) -> Self {
+        SyntaxPair {
+            key: pair.identifier.clone(),
+            before_sign: Vec::new(),
+            sign: pair.sign.clone(),
+            before_value: Vec::new(),
+            value: (&pair.value).into(),
+        }
+    }
+}
+
+impl From<&ConfigValue> for SyntaxValue {
+    fn from(value: &ConfigValue) -> Self {
+        let values = |values: &[ConfigElement]| {
+            values
+                .iter()
+                .map(|element| SyntaxNode::Value((&element.value).into()))
+                .collect()
+        };
+
+        match value {
+            ConfigValue::Object(pairs) => SyntaxValue::Block(
+                pairs
+                    .iter()
+                    .map(|pair| SyntaxNode::Pair(pair.into()))
+                    .collect(),
+            ),
+            ConfigValue::Array(array) => SyntaxValue::Block(values(array)),
+            ConfigValue::String(string) => SyntaxValue::Scalar(format!("\"{}\"", string)),
+            ConfigValue::Number(number) => SyntaxValue::Scalar(number.to_string()),
+            ConfigValue::Identifier(identifier) => SyntaxValue::Scalar(identifier.clone()),
+            ConfigValue::Date(year, month, day) => {
+                SyntaxValue::Scalar(format!("{}.{}.{}", year, month, day))
+            }
+            ConfigValue::Variable(name) => SyntaxValue::Scalar(format!("@{}", name)),
+            ConfigValue::Math(expression) => SyntaxValue::Scalar(format!("@[{}]", expression)),
+            ConfigValue::Named(name, array) => SyntaxValue::Named {
+                name: name.clone(),
+                before_block: Vec::new(),
+                block: values(array),
+            },
+        }
+    }
+}
+
+fn write_nodes(f: &mut Formatter, nodes: &[SyntaxNode]) -> fmt::Result {
+    for node in nodes {
+        write!(f, "{}", node)?;
+    }
+    Ok(())
+}
+
+pub fn parse_syntax_tree(file: &str) -> Result<SyntaxTree, Error<Rule>> {
+    let parsed = CstParser::parse(Rule::file, file)?;
+
+    Ok(SyntaxTree {
+        nodes: parse_nodes(parsed),
+    })
+}
+
+fn parse_nodes(pairs: Pairs<Rule>) -> Vec<SyntaxNode> {
+    pairs
+        .filter(|pair| pair.as_rule() != Rule::EOI)
+        .map(parse_node)
+        .collect()
+}
+
+fn parse_node(pair: Pair<Rule>) -> SyntaxNode {
+    match pair.as_rule() {
+        Rule::whitespace => SyntaxNode::Whitespace(pair.as_str().to_owned()),
+        Rule::comment => SyntaxNode::Comment(pair.as_str().to_owned()),
+        Rule::pair => SyntaxNode::Pair(parse_pair(pair)),
+        _ => SyntaxNode::Value(parse_value(pair)),
+    }
+}
+
+fn parse_pair(pair: Pair<Rule>) -> SyntaxPair {
+    let mut inner_rules = pair.into_inner();
+    let key = inner_rules.next().unwrap().as_str().to_owned();
+
+    let mut before_sign = Vec::new();
+    let sign = loop {
+        let next = inner_rules.next().unwrap();
+        match next.as_rule() {
+            Rule::sign => break next.as_str().to_owned(),
+            _ => before_sign.push(parse_node(next)),
+        }
+    };
+
+    let mut before_value = Vec::new();
+    let value = loop {
+        let next = inner_rules.next().unwrap();
+        match next.as_rule() {
+            Rule::whitespace | Rule::comment => before_value.push(parse_node(next)),
+            _ => break parse_value(next),
+        }
+    };
+
+    SyntaxPair {
+        key,
+        before_sign,
+        sign,
+        before_value,
+        value,
+    }
+}
+
+fn parse_value(pair: Pair<Rule>) -> SyntaxValue {
+    match pair.as_rule() {
+        Rule::scalar => SyntaxValue::Scalar(pair.as_str().to_owned()),
+        Rule::block => SyntaxValue::Block(parse_nodes(pair.into_inner())),
+        Rule::named => {
+            let mut inner_rules = pair.into_inner();
+            let name = inner_rules.next().unwrap().as_str().to_owned();
+
+            let mut before_block = Vec::new();
+            let block = loop {
+                let next = inner_rules.next().unwrap();
+                match next.as_rule() {
+                    Rule::block => break parse_nodes(next.into_inner()),
+                    _ => before_block.push(parse_node(next)),
+                }
+            };
+
+            SyntaxValue::Named {
+                name,
+                before_block,
+                block,
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_syntax_tree_round_trip() {
+        let input = r#"# Header comment
+
+key1 = "value1" # trailing comment
+key2=1.000
+key3 = {
+	key4 = 1444.11.11
+
+	key5 = { 1 2   3 }
+	# nested comment
+	color = rgb{ 255 0 0 }
+}
+key6 < -0.50
+"key 7" = "escaped \" quote"
+@my_var = 10
+key8 = @[ my_var * 2 ]
+"#;
+
+        let tree = parse_syntax_tree(input).unwrap();
+
+        assert_eq!(tree.to_string(), input);
+    }
+
+    #[test]
+    fn test_parse_syntax_tree_round_trip_crlf() {
+        let input = "key1 = {\r\n\tkey2 = yes # comment\r\n}\r\n\r\n";
+
+        let tree = parse_syntax_tree(input).unwrap();
+
+        assert_eq!(tree.to_string(), input);
+    }
+
+    #[test]
+    fn test_parse_syntax_tree_structure() {
+        let input = "key1 = 1.000 # one\nkey2 = { a b }";
+
+        let tree = parse_syntax_tree(input).unwrap();
+
+        assert_eq!(
+            tree.nodes,
+            vec![
+                SyntaxNode::Pair(SyntaxPair {
+                    key: "key1".to_string(),
+                    before_sign: vec![SyntaxNode::Whitespace(" ".to_string())],
+                    sign: "=".to_string(),
+                    before_value: vec![SyntaxNode::Whitespace(" ".to_string())],
+                    value: SyntaxValue::Scalar("1.000".to_string()),
+                }),
+                SyntaxNode::Whitespace(" ".to_string()),
+                SyntaxNode::Comment("# one".to_string()),
+                SyntaxNode::Whitespace("\n".to_string()),
+                SyntaxNode::Pair(SyntaxPair {
+                    key: "key2".to_string(),
+                    before_sign: vec![SyntaxNode::Whitespace(" ".to_string())],
+                    sign: "=".to_string(),
+                    before_value: vec![SyntaxNode::Whitespace(" ".to_string())],
+                    value: SyntaxValue::Block(vec![
+                        SyntaxNode::Whitespace(" ".to_string()),
+                        SyntaxNode::Value(SyntaxValue::Scalar("a".to_string())),
+                        SyntaxNode::Whitespace(" ".to_string()),
+                        SyntaxNode::Value(SyntaxValue::Scalar("b".to_string())),
+                        SyntaxNode::Whitespace(" ".to_string()),
+                    ]),
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_syntax_tree_error() {
+        assert!(parse_syntax_tree("key1 = { key2 = 1").is_err());
+    }
+}
@@ -0,0 +1,520 @@
+//! Edits on a [`SyntaxTree`], which leave everything they do not touch as it was.
+//!
+//! Pairs are addressed by a path of indices: the index of the pair among the pairs at the
+//! top of the file, then among the pairs in its block, and so on. Comments, whitespace and
+//! values without keys are not counted, so the indices match those of
+//! [`ConfigValue::Object`](crate::parser::ConfigValue::Object).
+
+use std::fmt::{self, Display, Formatter};
+
+use crate::cst::{parse_syntax_tree, SyntaxNode, SyntaxPair, SyntaxTree, SyntaxValue};
+
+const SIGNS: [&str; 8] = ["<=", ">=", "!=", "?=", "=", "<", ">", "+"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Edit {
+    SetKey {
+        path: Vec<usize>,
+        key: String,
+    },
+    SetSign {
+        path: Vec<usize>,
+        sign: String,
+    },
+    SetValue {
+        path: Vec<usize>,
+        value: SyntaxValue,
+    },
+    /// Inserts `pair` so it ends up at `path`, moving the pairs from there on down by one.
+    Insert {
+        path: Vec<usize>,
+        pair: SyntaxPair,
+        /// Comment to put after the pair, on the same line.
+        comment: Option<String>,
+    },
+    /// Removes the pair at `path`, and the comment after it on the same line.
+    Remove {
+        path: Vec<usize>,
+    },
+    /// Moves the pair at `from`, with its comment, so it ends up at `to`. `to` is a path in
+    /// the tree after the pair was taken out.
+    Move {
+        from: Vec<usize>,
+        to: Vec<usize>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditError {
+    /// No pair at the path, or no block to insert into.
+    NoSuchPair(Vec<usize>),
+    /// Text that does not parse as what it should be.
+    Invalid(String),
+}
+
+impl Display for EditError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            EditError::NoSuchPair(path) => write!(f, "no pair at {:?}", path),
+            EditError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+impl Edit {
+    pub fn path(&self) -> &[usize] {
+        match self {
+            Edit::SetKey { path, .. }
+            | Edit::SetSign { path, .. }
+            | Edit::SetValue { path, .. }
+            | Edit::Insert { path, .. }
+            | Edit::Remove { path }
+            | Edit::Move { from: path, .. } => path,
+        }
+    }
+}
+
+/// Applies `edit` to `tree`, returning the edit that undoes it.
+pub fn apply(tree: &mut SyntaxTree, edit: &Edit) -> Result<Edit, EditError> {
+    let no_such_pair = || EditError::NoSuchPair(edit.path().to_vec());
+
+    match edit {
+        Edit::SetKey { path, key } => {
+            let pair = pair_mut(&mut tree.nodes, path).ok_or_else(no_such_pair)?;
+            let key = std::mem::replace(&mut pair.key, key.clone());
+            Ok(Edit::SetKey {
+                path: path.clone(),
+                key,
+            })
+        }
+        Edit::SetSign { path, sign } => {
+            let pair = pair_mut(&mut tree.nodes, path).ok_or_else(no_such_pair)?;
+            let sign = std::mem::replace(&mut pair.sign, sign.clone());
+            Ok(Edit::SetSign {
+                path: path.clone(),
+                sign,
+            })
+        }
+        Edit::SetValue { path, value } => {
+            let pair = pair_mut(&mut tree.nodes, path).ok_or_else(no_such_pair)?;
+            let value = std::mem::replace(&mut pair.value, value.clone());
+            Ok(Edit::SetValue {
+                path: path.clone(),
+                value,
+            })
+        }
+        Edit::Insert {
+            path,
+            pair,
+            comment,
+        } => {
+            let (index, parent) = path.split_last().ok_or_else(no_such_pair)?;
+            let nodes = block_mut(&mut tree.nodes, parent).ok_or_else(no_such_pair)?;
+            insert(nodes, *index, pair.clone(), comment.clone()).ok_or_else(no_such_pair)?;
+            Ok(Edit::Remove { path: path.clone() })
+        }
+        Edit::Remove { path } => {
+            let (index, parent) = path.split_last().ok_or_else(no_such_pair)?;
+            let nodes = block_mut(&mut tree.nodes, parent).ok_or_else(no_such_pair)?;
+            let (pair, comment) = remove(nodes, *index).ok_or_else(no_such_pair)?;
+            Ok(Edit::Insert {
+                path: path.clone(),
+                pair,
+                comment,
+            })
+        }
+        Edit::Move { from, to } => {
+            let undo = apply(tree, &Edit::Remove { path: from.clone() })?;
+            let Edit::Insert { pair, comment, .. } = undo.clone() else {
+                unreachable!();
+            };
+
+            let moved = apply(
+                tree,
+                &Edit::Insert {
+                    path: to.clone(),
+                    pair,
+                    comment,
+                },
+            );
+            if moved.is_err() {
+                apply(tree, &undo)?;
+                return Err(EditError::NoSuchPair(to.clone()));
+            }
+
+            Ok(Edit::Move {
+                from: to.clone(),
+                to: from.clone(),
+            })
+        }
+    }
+}
+
+/// Parses a key as typed by the user.
+pub fn parse_key(text: &str) -> Result<String, EditError> {
+    let pair = parse_pair(&format!("{} = 0", text.trim()))
+        .map_err(|_| EditError::Invalid(format!("`{}` is not a valid key", text)))?;
+    Ok(pair.key)
+}
+
+pub fn parse_sign(text: &str) -> Result<String, EditError> {
+    let text = text.trim();
+    if SIGNS.contains(&text) {
+        Ok(text.to_owned())
+    } else {
+        Err(EditError::Invalid(format!(
+            "`{}` is not a valid sign",
+            text
+        )))
+    }
+}
+
+/// Parses a value as typed by the user, such as `10`, `"text"` or `{ 1 2 3 }`.
+pub fn parse_value(text: &str) -> Result<SyntaxValue, EditError> {
+    let pair = parse_pair(&format!("key = {}", text.trim()))
+        .map_err(|_| EditError::Invalid(format!("`{}` is not a valid value", text)))?;
+    Ok(pair.value)
+}
+
+/// Parses a single pair, such as `add_prestige = 10`.
+pub fn parse_pair(text: &str) -> Result<SyntaxPair, EditError> {
+    let invalid = || EditError::Invalid(format!("`{}` is not a single pair", text));
+    let tree = parse_syntax_tree(text.trim()).map_err(|_| invalid())?;
+
+    match &tree.nodes[..] {
+        [SyntaxNode::Pair(pair)] => Ok(pair.clone()),
+        _ => Err(invalid()),
+    }
+}
+
+/// Index in `nodes` of the pair with the given index among pairs.
+fn position(nodes: &[SyntaxNode], index: usize) -> Option<usize> {
+    nodes
+        .iter()
+        .enumerate()
+        .filter(|(_, node)| matches!(node, SyntaxNode::Pair(_)))
+        .nth(index)
+        .map(|(position, _)| position)
+}
+
+fn pair_mut<'a>(nodes: &'a mut Vec<SyntaxNode>, path: &[usize]) -> Option<&'a mut SyntaxPair> {
+    let (index, parent) = path.split_last()?;
+    let nodes = block_mut(nodes, parent)?;
+    let position = position(nodes, *index)?;
+
+    match &mut nodes[position] {
+        SyntaxNode::Pair(pair) => Some(pair),
+        _ => None,
+    }
+}
+
+/// The nodes in the block of the pair at `path`, or `nodes` itself for an empty path.
+fn block_mut<'a>(
+    nodes: &'a mut Vec<SyntaxNode>,
+    path: &[usize],
+) -> Option<&'a mut Vec<SyntaxNode>> {
+    let Some((index, rest)) = path.split_first() else {
+        return Some(nodes);
+    };
+
+    let position = position(nodes, *index)?;
+    match &mut nodes[position] {
+        SyntaxNode::Pair(SyntaxPair {
+            value: SyntaxValue::Block(children),
+            ..
+        }) => block_mut(children, rest),
+        _ => None,
+    }
+}
+
+/// Position in `nodes` just after the pair at `position` and the comment following it on
+/// the same line, if any.
+fn end_of_line(nodes: &[SyntaxNode], position: usize) -> usize {
+    match &nodes[position + 1..] {
+        [SyntaxNode::Comment(_), ..] => position + 2,
+        [SyntaxNode::Whitespace(whitespace), SyntaxNode::Comment(_), ..]
+            if !whitespace.contains('\n') =>
+        {
+            position + 3
+        }
+        _ => position + 1,
+    }
+}
+
+/// Inserts `pair` as the pair at `index`, indented like its neighbours.
+fn insert(
+    nodes: &mut Vec<SyntaxNode>,
+    index: usize,
+    pair: SyntaxPair,
+    comment: Option<String>,
+) -> Option<()> {
+    let pairs = nodes
+        .iter()
+        .filter(|node| matches!(node, SyntaxNode::Pair(_)))
+        .count();
+    if index > pairs {
+        return None;
+    }
+
+    let mut line = vec![SyntaxNode::Pair(pair)];
+    if let Some(comment) = comment {
+        line.push(SyntaxNode::Whitespace(" ".to_owned()));
+        line.push(SyntaxNode::Comment(comment));
+    }
+
+    // The whitespace before an existing pair, which is the line break and indentation for
+    // pairs on their own line, or a space for pairs on one line. The first pair of a file
+    // has nothing before it, so the whitespace after it is used instead.
+    let separator = |position: usize| match position.checked_sub(1).map(|i| &nodes[i]) {
+        Some(SyntaxNode::Whitespace(whitespace)) => whitespace.clone(),
+        _ => match nodes.get(end_of_line(nodes, position)) {
+            Some(SyntaxNode::Whitespace(whitespace)) => whitespace.clone(),
+            _ => " ".to_owned(),
+        },
+    };
+
+    if index < pairs {
+        let position = position(nodes, index)?;
+        line.push(SyntaxNode::Whitespace(separator(position)));
+        nodes.splice(position..position, line);
+    } else if pairs > 0 {
+        let position = position(nodes, pairs - 1)?;
+        line.insert(0, SyntaxNode::Whitespace(separator(position)));
+        let end = end_of_line(nodes, position);
+        nodes.splice(end..end, line);
+    } else if nodes.is_empty() {
+        nodes.extend(line);
+    } else {
+        // An empty block such as `{ }`, or a file with only comments.
+        let separator = match nodes.last() {
+            Some(SyntaxNode::Whitespace(whitespace)) if whitespace.contains('\n') => {
+                whitespace.clone()
+            }
+            _ => " ".to_owned(),
+        };
+        nodes.extend(line);
+        nodes.push(SyntaxNode::Whitespace(separator));
+    }
+
+    Some(())
+}
+
+/// Removes the pair at `index` together with its comment and the whitespace in front of
+/// it. Returns the pair and the comment.
+fn remove(nodes: &mut Vec<SyntaxNode>, index: usize) -> Option<(SyntaxPair, Option<String>)> {
+    let position = position(nodes, index)?;
+    let end = end_of_line(nodes, position);
+
+    let mut removed = nodes.drain(position..end);
+    let Some(SyntaxNode::Pair(pair)) = removed.next() else {
+        unreachable!();
+    };
+    let comment = removed.find_map(|node| match node {
+        SyntaxNode::Comment(comment) => Some(comment),
+        _ => None,
+    });
+    drop(removed);
+
+    let before = position
+        .checked_sub(1)
+        .filter(|&i| matches!(nodes[i], SyntaxNode::Whitespace(_)));
+    let after = Some(position).filter(|&i| matches!(nodes.get(i), Some(SyntaxNode::Whitespace(_))));
+
+    match (before, after) {
+        // Keep the whitespace after the pair, so the next pair or the closing brace stays
+        // where it was.
+        (Some(before), Some(_)) => {
+            nodes.remove(before);
+        }
+        (None, Some(after)) if position == 0 => {
+            nodes.remove(after);
+        }
+        _ => {}
+    }
+
+    Some((pair, comment))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &str = "# Sweden\ncountry = {\n\ttag = SWE # the tag\n\tcapital = 1\n\tideas = { }\n}\nother = yes\n";
+
+    fn apply_all(input: &str, edits: &[Edit]) -> (String, Vec<Edit>) {
+        let mut tree = parse_syntax_tree(input).unwrap();
+        let undo = edits
+            .iter()
+            .map(|edit| apply(&mut tree, edit).unwrap())
+            .collect();
+        (tree.to_string(), undo)
+    }
+
+    #[test]
+    fn test_set() {
+        let (output, _) = apply_all(
+            INPUT,
+            &[
+                Edit::SetValue {
+                    path: vec![0, 1],
+                    value: parse_value("2").unwrap(),
+                },
+                Edit::SetKey {
+                    path: vec![0, 0],
+                    key: parse_key("owner").unwrap(),
+                },
+                Edit::SetSign {
+                    path: vec![1],
+                    sign: parse_sign("!=").unwrap(),
+                },
+            ],
+        );
+
+        assert_eq!(
+            output,
+            "# Sweden\ncountry = {\n\towner = SWE # the tag\n\tcapital = 2\n\tideas = { }\n}\nother != yes\n"
+        );
+    }
+
+    #[test]
+    fn test_insert() {
+        let pair = parse_pair("add_core = FIN").unwrap();
+        let (output, _) = apply_all(
+            INPUT,
+            &[
+                Edit::Insert {
+                    path: vec![0, 1],
+                    pair: pair.clone(),
+                    comment: None,
+                },
+                Edit::Insert {
+                    path: vec![0, 4],
+                    pair: pair.clone(),
+                    comment: None,
+                },
+                Edit::Insert {
+                    path: vec![0, 3, 0],
+                    pair: pair.clone(),
+                    comment: None,
+                },
+                Edit::Insert {
+                    path: vec![2],
+                    pair,
+                    comment: Some("# new".to_owned()),
+                },
+            ],
+        );
+
+        assert_eq!(
+            output,
+            "# Sweden\ncountry = {\n\ttag = SWE # the tag\n\tadd_core = FIN\n\tcapital = 1\n\tideas = { add_core = FIN }\n\tadd_core = FIN\n}\nother = yes\nadd_core = FIN # new\n"
+        );
+    }
+
+    #[test]
+    fn test_remove() {
+        let (output, _) = apply_all(
+            INPUT,
+            &[
+                Edit::Remove { path: vec![0, 1] },
+                Edit::Remove { path: vec![0, 0] },
+            ],
+        );
+
+        assert_eq!(
+            output,
+            "# Sweden\ncountry = {\n\tideas = { }\n}\nother = yes\n"
+        );
+    }
+
+    #[test]
+    fn test_move() {
+        let (output, undo) = apply_all(
+            INPUT,
+            &[
+                Edit::Move {
+                    from: vec![0, 0],
+                    to: vec![0, 1],
+                },
+                Edit::Move {
+                    from: vec![1],
+                    to: vec![0, 2, 0],
+                },
+            ],
+        );
+
+        assert_eq!(
+            output,
+            "# Sweden\ncountry = {\n\tcapital = 1\n\ttag = SWE # the tag\n\tideas = { other = yes }\n}\n"
+        );
+        assert_eq!(
+            undo[1],
+            Edit::Move {
+                from: vec![0, 2, 0],
+                to: vec![1],
+            }
+        );
+
+        let mut tree = parse_syntax_tree(INPUT).unwrap();
+        let moved = apply(
+            &mut tree,
+            &Edit::Move {
+                from: vec![0, 0],
+                to: vec![0, 5],
+            },
+        );
+        assert_eq!(moved, Err(EditError::NoSuchPair(vec![0, 5])));
+        assert_eq!(tree.to_string(), INPUT);
+    }
+
+    #[test]
+    fn test_undo() {
+        let edits = [
+            Edit::SetValue {
+                path: vec![0, 1],
+                value: parse_value("{ 1 2 }").unwrap(),
+            },
+            Edit::Remove { path: vec![0, 0] },
+            Edit::Insert {
+                path: vec![1],
+                pair: parse_pair("a = b").unwrap(),
+                comment: None,
+            },
+        ];
+        let mut tree = parse_syntax_tree(INPUT).unwrap();
+        let undo: Vec<Edit> = edits
+            .iter()
+            .map(|edit| apply(&mut tree, edit).unwrap())
+            .collect();
+
+        for edit in undo.iter().rev() {
+            apply(&mut tree, edit).unwrap();
+        }
+
+        assert_eq!(tree.to_string(), INPUT);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut tree = parse_syntax_tree(INPUT).unwrap();
+
+        assert!(parse_value("{ 1 2").is_err());
+        assert!(parse_key("a = b").is_err());
+        assert!(parse_sign("~").is_err());
+        assert_eq!(
+            apply(&mut tree, &Edit::Remove { path: vec![5] }),
+            Err(EditError::NoSuchPair(vec![5]))
+        );
+        assert!(apply(
+            &mut tree,
+            &Edit::Insert {
+                path: vec![1, 0],
+                pair: parse_pair("a = b").unwrap(),
+                comment: None,
+            }
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,264 @@
+//! Export of parsed script to JSON, YAML and TOML.
+//!
+//! All three formats go through the same JSON data model:
+//!
+//! | Clausewitz                  | JSON                                          |
+//! |-----------------------------|-----------------------------------------------|
+//! | `key = value`               | `"key": value`                                |
+//! | `key < value`               | `"key": { "$op": "<", "value": value }`       |
+//! | `key = a key = b`           | `"key": { "$repeat": [a, b] }`                |
+//! | `{ key = value }`           | `{ "key": value }`                            |
+//! | `{ 1 2 3 }`                 | `[1, 2, 3]`                                   |
+//! | `rgb { 1 2 3 }`             | `{ "$named": "rgb", "values": [1, 2, 3] }`    |
+//! | `"text"`, `identifier`      | `"text"`, `"identifier"`                      |
+//! | `42`, `1.5`                 | `42`, `1.5`                                   |
+//! | `1444.11.11`                | `"1444.11.11"`                                |
+//! | `@my_var`                   | `"@my_var"`                                   |
+//! | `@[ my_var * 2 ]`           | `{ "$math": " my_var * 2 " }`                 |
+//!
+//! Keys keep the order they first appear in. A repeated key is listed once, at its first
+//! occurrence, with every value in `$repeat`; its position relative to other keys is lost.
+//! Each value in `$repeat` can itself be an `$op` object when it uses another sign.
+//! Strings and identifiers both become JSON strings, so quoting is not preserved.
+//! Scripted variables are kept as written, which reads back as a variable. Inline math
+//! is kept apart in `$math`, as the string it is written as may just as well be quoted
+//! text. Variables and math are not resolved.
+
+use std::fmt::{self, Display, Formatter};
+
+use serde_json::{json, Map, Number, Value};
+
+use crate::parser::{ConfigElement, ConfigPair, ConfigValue};
+
+pub const OP: &str = "$op";
+pub const REPEAT: &str = "$repeat";
+pub const NAMED: &str = "$named";
+pub const MATH: &str = "$math";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ExportFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl ExportFormat {
+    /// Picks the format matching a file extension, such as `yml`.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "json" => Some(ExportFormat::Json),
+            "yaml" | "yml" => Some(ExportFormat::Yaml),
+            "toml" => Some(ExportFormat::Toml),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Yaml => "yaml",
+            ExportFormat::Toml => "toml",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    Yaml(serde_yaml::Error),
+    Toml(toml::ser::Error),
+}
+
+impl Display for ExportError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ExportError::Yaml(e) => write!(f, "{}", e),
+            ExportError::Toml(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+/// Converts pairs to a JSON object, following the mapping in the module documentation.
+pub fn to_json(pairs: &[ConfigPair]) -> Value {
+    let mut object = Map::new();
+
+    for pair in pairs {
+        let value = match pair.sign.as_str() {
+            "=" => value_to_json(&pair.value),
+            sign => json!({ OP: sign, "value": value_to_json(&pair.value) }),
+        };
+
+        match object.get_mut(&pair.identifier) {
+            None => {
+                object.insert(pair.identifier.clone(), value);
+            }
+            Some(Value::Object(repeat)) if is_repeat(repeat) => {
+                repeat[REPEAT].as_array_mut().unwrap().push(value);
+            }
+            Some(existing) => {
+                *existing = json!({ REPEAT: [existing.take(), value] });
+            }
+        }
+    }
+
+    Value::Object(object)
+}
+
+/// Whether `object` collects the values of a repeated key. A single `{ $repeat = ... }`
+/// object in the script would look the same, but keys starting with `$` only appear in
+/// scripted effect parameters.
+fn is_repeat(object: &Map<String, Value>) -> bool {
+    object.len() == 1 && object.get(REPEAT).is_some_and(Value::is_array)
+}
+
+fn value_to_json(value: &ConfigValue) -> Value {
+    match value {
+        ConfigValue::Object(pairs) => to_json(pairs),
+        ConfigValue::Array(values) => Value::Array(elements_to_json(values)),
+        ConfigValue::String(string) | ConfigValue::Identifier(string) => {
+            Value::String(string.clone())
+        }
+        ConfigValue::Number(number) => number_to_json(*number),
+        ConfigValue::Date(year, month, day) => Value::String(format!("{}.{}.{}", year, month, day)),
+        ConfigValue::Variable(_) => Value::String(value.to_string()),
+        ConfigValue::Math(expression) => json!({ MATH: expression }),
+        ConfigValue::Named(name, values) => json!({
+            NAMED: name,
+            "values": elements_to_json(values),
+        }),
+    }
+}
+
+fn elements_to_json(elements: &[ConfigElement]) -> Vec<Value> {
+    elements
+        .iter()
+        .map(|element| value_to_json(&element.value))
+        .collect()
+}
+
+/// Whole numbers are written as integers, so `42` does not become `42.0`.
+fn number_to_json(number: f64) -> Value {
+    if number.fract() == 0.0 && number.abs() < i64::MAX as f64 {
+        Value::Number((number as i64).into())
+    } else {
+        Number::from_f64(number).map_or(Value::Null, Value::Number)
+    }
+}
+
+/// Writes a value built by [`to_json`] in the given format.
+pub fn serialize(value: &Value, format: ExportFormat) -> Result<String, ExportError> {
+    match format {
+        ExportFormat::Json => Ok(serde_json::to_string_pretty(value).unwrap() + "\n"),
+        ExportFormat::Yaml => serde_yaml::to_string(value).map_err(ExportError::Yaml),
+        ExportFormat::Toml => toml::to_string_pretty(value).map_err(ExportError::Toml),
+    }
+}
+
+pub fn export(pairs: &[ConfigPair], format: ExportFormat) -> Result<String, ExportError> {
+    serialize(&to_json(pairs), format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_config_file;
+
+    const INPUT: &str = r#"
+        name = "Sweden"
+        capital = 1
+        add_core = SWE
+        add_core = FIN
+        color = rgb { 10 20 30 }
+        1444.11.11 = {
+            monarch = { adm = 3 dip = 2.5 }
+            provinces = { 1 2 3 }
+        }
+        num_of_cities > 5
+        date = 1444.11.11
+        scaled = @[ base * 2 ]
+        unscaled = @base
+    "#;
+
+    #[test]
+    fn test_to_json() {
+        let pairs = parse_config_file(INPUT).unwrap();
+
+        assert_eq!(
+            to_json(&pairs),
+            json!({
+                "name": "Sweden",
+                "capital": 1,
+                "add_core": { "$repeat": ["SWE", "FIN"] },
+                "color": { "$named": "rgb", "values": [10, 20, 30] },
+                "1444.11.11": {
+                    "monarch": { "adm": 3, "dip": 2.5 },
+                    "provinces": [1, 2, 3]
+                },
+                "num_of_cities": { "$op": ">", "value": 5 },
+                "date": "1444.11.11",
+                "scaled": { "$math": " base * 2 " },
+                "unscaled": "@base"
+            })
+        );
+    }
+
+    #[test]
+    fn test_to_json_key_order() {
+        let pairs = parse_config_file(INPUT).unwrap();
+        let json = to_json(&pairs);
+        let keys: Vec<&String> = json.as_object().unwrap().keys().collect();
+
+        assert_eq!(
+            keys,
+            [
+                "name",
+                "capital",
+                "add_core",
+                "color",
+                "1444.11.11",
+                "num_of_cities",
+                "date",
+                "scaled",
+                "unscaled"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_to_json_repeat_with_op() {
+        let pairs = parse_config_file("a = 1\na < 2\na = 3\n").unwrap();
+
+        assert_eq!(
+            to_json(&pairs),
+            json!({ "a": { "$repeat": [1, { "$op": "<", "value": 2 }, 3] } })
+        );
+    }
+
+    #[test]
+    fn test_export_formats() {
+        let pairs = parse_config_file("a = { b = yes c = 1.5 }\nd = { 1 2 }\n").unwrap();
+
+        assert_eq!(
+            export(&pairs, ExportFormat::Json).unwrap(),
+            "{\n  \"a\": {\n    \"b\": \"yes\",\n    \"c\": 1.5\n  },\n  \"d\": [\n    1,\n    2\n  ]\n}\n"
+        );
+        assert_eq!(
+            export(&pairs, ExportFormat::Yaml).unwrap(),
+            "a:\n  b: yes\n  c: 1.5\nd:\n- 1\n- 2\n"
+        );
+        assert_eq!(
+            export(&pairs, ExportFormat::Toml).unwrap(),
+            "d = [\n    1,\n    2,\n]\n\n[a]\nb = \"yes\"\nc = 1.5\n"
+        );
+    }
+
+    #[test]
+    fn test_export_format_from_extension() {
+        assert_eq!(
+            ExportFormat::from_extension("YML"),
+            Some(ExportFormat::Yaml)
+        );
+        assert_eq!(ExportFormat::from_extension("txt"), None);
+    }
+}
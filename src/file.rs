@@ -1,9 +1,139 @@
-use std::{fs, path::PathBuf};
-
-pub fn read_file(path: &PathBuf) -> String {
-    fs::read_to_string(path)
-        .unwrap()
-        .chars()
-        .filter(|c| c.is_ascii())
-        .collect::<String>()
+use std::fmt::{self, Display, Formatter};
+use std::{fs, io, path::PathBuf};
+
+use encoding_rs::WINDOWS_1252;
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Text encodings used by Clausewitz game files.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    /// UTF-8 starting with a byte order mark, as required for localisation files.
+    Utf8Bom,
+    /// Windows-1252, used by older games for script files. Also covers Latin-1.
+    Windows1252,
+}
+
+impl Display for Encoding {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Encoding::Utf8 => write!(f, "UTF-8"),
+            Encoding::Utf8Bom => write!(f, "UTF-8 with BOM"),
+            Encoding::Windows1252 => write!(f, "Windows-1252"),
+        }
+    }
+}
+
+/// Decoded contents of a file, together with the encoding they were stored in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceFile {
+    pub text: String,
+    pub encoding: Encoding,
+}
+
+pub fn read_file(path: &PathBuf) -> io::Result<SourceFile> {
+    Ok(decode(&fs::read(path)?))
+}
+
+/// Writes `text` to `path` in the given encoding, so edited files keep the encoding they
+/// were read in.
+pub fn write_file(path: &PathBuf, text: &str, encoding: Encoding) -> io::Result<()> {
+    let bytes = encode(text, encoding).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "text contains characters that cannot be written as {}",
+                encoding
+            ),
+        )
+    })?;
+
+    fs::write(path, bytes)
+}
+
+/// Detects the encoding of `bytes` and decodes them. Anything that is not valid UTF-8 is
+/// read as Windows-1252, which maps every byte to a character.
+pub fn decode(bytes: &[u8]) -> SourceFile {
+    if let Some(bytes) = bytes.strip_prefix(UTF8_BOM) {
+        if let Ok(text) = std::str::from_utf8(bytes) {
+            return SourceFile {
+                text: text.to_owned(),
+                encoding: Encoding::Utf8Bom,
+            };
+        }
+    }
+
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return SourceFile {
+            text: text.to_owned(),
+            encoding: Encoding::Utf8,
+        };
+    }
+
+    let (text, _) = WINDOWS_1252.decode_without_bom_handling(bytes);
+    SourceFile {
+        text: text.into_owned(),
+        encoding: Encoding::Windows1252,
+    }
+}
+
+/// Encodes `text` in the given encoding. Returns `None` if the text contains characters
+/// the encoding cannot represent.
+pub fn encode(text: &str, encoding: Encoding) -> Option<Vec<u8>> {
+    match encoding {
+        Encoding::Utf8 => Some(text.as_bytes().to_vec()),
+        Encoding::Utf8Bom => Some([UTF8_BOM, text.as_bytes()].concat()),
+        Encoding::Windows1252 => {
+            let (bytes, _, had_errors) = WINDOWS_1252.encode(text);
+            (!had_errors).then(|| bytes.into_owned())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_utf8() {
+        let file = decode("name = \"Qu\u{e9}bec\"".as_bytes());
+
+        assert_eq!(file.encoding, Encoding::Utf8);
+        assert_eq!(file.text, "name = \"Qu\u{e9}bec\"");
+    }
+
+    #[test]
+    fn test_decode_utf8_bom() {
+        let file = decode(b"\xEF\xBB\xBFl_english:\n W\xC3\xBCrttemberg:0 \"\"");
+
+        assert_eq!(file.encoding, Encoding::Utf8Bom);
+        assert_eq!(file.text, "l_english:\n W\u{fc}rttemberg:0 \"\"");
+    }
+
+    #[test]
+    fn test_decode_windows1252() {
+        let file = decode(b"name = \"W\xFCrttemberg\" # \x80");
+
+        assert_eq!(file.encoding, Encoding::Windows1252);
+        assert_eq!(file.text, "name = \"W\u{fc}rttemberg\" # \u{20ac}");
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        for bytes in [
+            &b"name = \"Qu\xC3\xA9bec\""[..],
+            &b"\xEF\xBB\xBFname = \"Qu\xC3\xA9bec\""[..],
+            &b"name = \"Qu\xE9bec\""[..],
+        ] {
+            let file = decode(bytes);
+            assert_eq!(encode(&file.text, file.encoding).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn test_encode_unrepresentable() {
+        assert_eq!(encode("\u{4e2d}", Encoding::Windows1252), None);
+    }
 }
@@ -0,0 +1,382 @@
+//! Canonical formatting of Clausewitz script.
+//!
+//! The formatter works on the lossless [`SyntaxTree`], so comments survive formatting.
+//! Parsed [`ConfigPair`]s are formatted by converting them to a tree first.
+
+use crate::cst::{SyntaxNode, SyntaxPair, SyntaxTree, SyntaxValue};
+use crate::parser::{ConfigPair, ConfigValue};
+
+/// Width of a tab when deciding whether a folded block fits on its line.
+const TAB_WIDTH: usize = 4;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Indent {
+    Tabs,
+    Spaces(usize),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BraceStyle {
+    /// `key = {` with the brace on the same line as the key.
+    SameLine,
+    /// The opening brace on its own line, below the key.
+    NextLine,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CommentPlacement {
+    /// Comments after a pair stay on the same line as the pair.
+    Preserve,
+    /// Comments after a pair are moved onto their own line above the pair.
+    OwnLine,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct FormatOptions {
+    pub indent: Indent,
+    pub brace_style: BraceStyle,
+    /// Blocks of plain values, such as `{ 1 2 3 }`, are kept on one line while the line
+    /// stays within this many characters. Zero puts every value on its own line.
+    pub fold_width: usize,
+    pub comments: CommentPlacement,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            indent: Indent::Tabs,
+            brace_style: BraceStyle::SameLine,
+            fold_width: 80,
+            comments: CommentPlacement::Preserve,
+        }
+    }
+}
+
+pub fn format_syntax_tree(tree: &SyntaxTree, options: &FormatOptions) -> String {
+    let mut printer = Printer::new(options);
+    printer.lines(&tree.nodes);
+    printer.out
+}
+
+pub fn format_pairs(pairs: &[ConfigPair], options: &FormatOptions) -> String {
+    let tree = SyntaxTree {
+        nodes: pairs
+            .iter()
+            .map(|pair| SyntaxNode::Pair(pair.into()))
+            .collect(),
+    };
+
+    format_syntax_tree(&tree, options)
+}
+
+/// Formats a single value, without a trailing line break.
+pub fn format_value(value: &ConfigValue, options: &FormatOptions) -> String {
+    let mut printer = Printer::new(options);
+    printer.value(&value.into(), false);
+    printer.out
+}
+
+enum Entry<'a> {
+    Pair(&'a SyntaxPair),
+    Value(&'a SyntaxValue),
+    Comment(&'a str),
+    /// A comment on the same line as the pair or value before it.
+    TrailingComment(&'a str),
+    BlankLine,
+}
+
+struct Printer<'a> {
+    options: &'a FormatOptions,
+    out: String,
+    depth: usize,
+}
+
+impl<'a> Printer<'a> {
+    fn new(options: &'a FormatOptions) -> Self {
+        Printer {
+            options,
+            out: String::new(),
+            depth: 0,
+        }
+    }
+
+    /// Sorts `nodes` into the lines they will be printed as, dropping the original
+    /// whitespace but remembering blank lines and which comments trail a pair.
+    fn entries<'n>(&self, nodes: &'n [SyntaxNode]) -> Vec<Entry<'n>> {
+        let mut entries = Vec::new();
+        let mut line_breaks = 0;
+        let mut after_item = false;
+
+        for node in nodes {
+            if let SyntaxNode::Whitespace(whitespace) = node {
+                let count = whitespace.matches('\n').count();
+                line_breaks += count;
+                after_item &= count == 0;
+                continue;
+            }
+
+            if line_breaks > 1 && !entries.is_empty() {
+                entries.push(Entry::BlankLine);
+            }
+            line_breaks = 0;
+
+            match node {
+                SyntaxNode::Comment(comment) if after_item => {
+                    entries.push(Entry::TrailingComment(comment));
+                    after_item = false;
+                }
+                SyntaxNode::Comment(comment) => entries.push(Entry::Comment(comment)),
+                SyntaxNode::Pair(pair) => {
+                    // Comments between the key and the value cannot stay there.
+                    for node in pair.before_sign.iter().chain(&pair.before_value) {
+                        if let SyntaxNode::Comment(comment) = node {
+                            entries.push(Entry::Comment(comment));
+                        }
+                    }
+                    entries.push(Entry::Pair(pair));
+                    after_item = true;
+                }
+                SyntaxNode::Value(value) => {
+                    entries.push(Entry::Value(value));
+                    after_item = true;
+                }
+                SyntaxNode::Whitespace(_) => unreachable!(),
+            }
+        }
+
+        if self.options.comments == CommentPlacement::OwnLine {
+            for i in 1..entries.len() {
+                if let Entry::TrailingComment(comment) = entries[i] {
+                    entries[i] = Entry::Comment(comment);
+                    entries.swap(i - 1, i);
+                }
+            }
+        }
+
+        entries
+    }
+
+    /// Prints `nodes` one entry per line at the current depth.
+    fn lines(&mut self, nodes: &[SyntaxNode]) {
+        let entries = self.entries(nodes);
+
+        for (i, entry) in entries.iter().enumerate() {
+            match entry {
+                Entry::BlankLine => {}
+                Entry::TrailingComment(_) => continue,
+                Entry::Comment(comment) => {
+                    self.indent();
+                    self.out.push_str(comment.trim_end());
+                }
+                Entry::Pair(pair) => {
+                    self.indent();
+                    self.pair(pair);
+                }
+                Entry::Value(value) => {
+                    self.indent();
+                    self.value(value, false);
+                }
+            }
+
+            if let Some(Entry::TrailingComment(comment)) = entries.get(i + 1) {
+                self.out.push(' ');
+                self.out.push_str(comment.trim_end());
+            }
+            self.out.push('\n');
+        }
+    }
+
+    fn pair(&mut self, pair: &SyntaxPair) {
+        self.out.push_str(&pair.key);
+        self.out.push(' ');
+        self.out.push_str(&pair.sign);
+        self.out.push(' ');
+        self.value(&pair.value, true);
+    }
+
+    fn value(&mut self, value: &SyntaxValue, after_sign: bool) {
+        match value {
+            SyntaxValue::Scalar(scalar) => self.out.push_str(scalar),
+            SyntaxValue::Block(nodes) => self.block(nodes, after_sign),
+            SyntaxValue::Named {
+                name,
+                before_block,
+                block,
+            } => {
+                self.out.push_str(name);
+                self.out.push(' ');
+
+                // Comments between the name and the block move into the block, like a
+                // comment after its opening brace.
+                let mut comments = before_block
+                    .iter()
+                    .filter(|node| matches!(node, SyntaxNode::Comment(_)))
+                    .peekable();
+                if comments.peek().is_none() {
+                    self.block(block, false);
+                } else {
+                    let nodes: Vec<SyntaxNode> = comments.chain(block).cloned().collect();
+                    self.block(&nodes, false);
+                }
+            }
+        }
+    }
+
+    fn block(&mut self, nodes: &[SyntaxNode], after_sign: bool) {
+        let entries = self.entries(nodes);
+
+        if entries.is_empty() {
+            self.out.push_str("{ }");
+            return;
+        }
+
+        if let Some(folded) = self.fold(&entries) {
+            self.out.push_str(&folded);
+            return;
+        }
+
+        if after_sign && self.options.brace_style == BraceStyle::NextLine {
+            self.out.pop();
+            self.out.push('\n');
+            self.indent();
+        }
+
+        self.out.push_str("{\n");
+        self.depth += 1;
+        self.lines(nodes);
+        self.depth -= 1;
+        self.indent();
+        self.out.push('}');
+    }
+
+    /// Returns the block on a single line, if it only holds plain values and fits.
+    fn fold(&self, entries: &[Entry]) -> Option<String> {
+        let mut folded = String::from("{");
+        for entry in entries {
+            match entry {
+                Entry::Value(SyntaxValue::Scalar(scalar)) => {
+                    folded.push(' ');
+                    folded.push_str(scalar);
+                }
+                _ => return None,
+            }
+        }
+        folded.push_str(" }");
+
+        let line = &self.out[self.out.rfind('\n').map_or(0, |i| i + 1)..];
+        let width = line
+            .chars()
+            .chain(folded.chars())
+            .map(|c| if c == '\t' { TAB_WIDTH } else { 1 })
+            .sum::<usize>();
+
+        (width <= self.options.fold_width).then_some(folded)
+    }
+
+    fn indent(&mut self) {
+        for _ in 0..self.depth {
+            match self.options.indent {
+                Indent::Tabs => self.out.push('\t'),
+                Indent::Spaces(count) => self.out.extend(std::iter::repeat_n(' ', count)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cst::parse_syntax_tree;
+    use crate::parser::parse_config_file;
+
+    fn format(input: &str, options: &FormatOptions) -> String {
+        format_syntax_tree(&parse_syntax_tree(input).unwrap(), options)
+    }
+
+    #[test]
+    fn test_format_nesting() {
+        let input = "key1={key2={key3=yes}   key4 = \"value4\"}\n\n\n\nkey5 =   1.000";
+
+        assert_eq!(
+            format(input, &FormatOptions::default()),
+            "key1 = {\n\tkey2 = {\n\t\tkey3 = yes\n\t}\n\tkey4 = \"value4\"\n}\n\nkey5 = 1.000\n"
+        );
+    }
+
+    #[test]
+    fn test_format_options() {
+        let input = "key1 = { key2 = { 1 2 3 } }";
+        let options = FormatOptions {
+            indent: Indent::Spaces(2),
+            brace_style: BraceStyle::NextLine,
+            fold_width: 0,
+            ..FormatOptions::default()
+        };
+
+        assert_eq!(
+            format(input, &options),
+            "key1 =\n{\n  key2 =\n  {\n    1\n    2\n    3\n  }\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_folding() {
+        let input = "key1 = { 1 2 3 }\nkey2 = { 1 2 3 4 5 6 7 8 9 }\ncolor = rgb {255 0 0}";
+        let options = FormatOptions {
+            fold_width: 24,
+            ..FormatOptions::default()
+        };
+
+        assert_eq!(
+            format(input, &options),
+            "key1 = { 1 2 3 }\nkey2 = {\n\t1\n\t2\n\t3\n\t4\n\t5\n\t6\n\t7\n\t8\n\t9\n}\ncolor = rgb { 255 0 0 }\n"
+        );
+    }
+
+    #[test]
+    fn test_format_comments() {
+        let input = "# header\nkey1 = { # opening\n\tkey2 = yes    # trailing\n\n# own line\n}\n";
+
+        assert_eq!(
+            format(input, &FormatOptions::default()),
+            "# header\nkey1 = {\n\t# opening\n\tkey2 = yes # trailing\n\n\t# own line\n}\n"
+        );
+
+        let options = FormatOptions {
+            comments: CommentPlacement::OwnLine,
+            ..FormatOptions::default()
+        };
+        assert_eq!(
+            format(input, &options),
+            "# header\nkey1 = {\n\t# opening\n\t# trailing\n\tkey2 = yes\n\n\t# own line\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_named_comments() {
+        let input = "color = rgb # red\n{ 255 0 0 }\n";
+
+        assert_eq!(
+            format(input, &FormatOptions::default()),
+            "color = rgb {\n\t# red\n\t255\n\t0\n\t0\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_pairs_round_trip() {
+        let input = r#"
+            key1 = { key2 = { key3 = "value3" } }
+            key4 = { 1 2 3 }
+            key5 = { { a = 1 } { a = 2 } }
+            color = rgb { 255 0 0 }
+            key6 >= -1.5
+            key7 = 1444.11.11
+            key8 = { }
+        "#;
+
+        let pairs = parse_config_file(input).unwrap();
+        let formatted = format_pairs(&pairs, &FormatOptions::default());
+
+        assert_eq!(parse_config_file(&formatted).unwrap(), pairs);
+    }
+}
@@ -1,68 +1,399 @@
-use std::{collections::HashMap, fs, path::PathBuf};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+use rayon::prelude::*;
 
 use crate::{
-    file::read_file,
-    parser::{parse_config_file, ConfigPair},
+    file::{read_file, Encoding},
+    localisation::{load_localisation_files, localisation_files, Localisation},
+    mods::{resolve_files, Layer},
+    parser::{parse_config_file_recovering, ConfigPair, Diagnostic},
+    profile::{Extensions, FileCategory, Profile},
+    report::{FileReport, FileStatus, LoadReport},
 };
 
-pub fn parse_game(path: &PathBuf) -> HashMap<String, Vec<ConfigPair>> {
-    let mut parsed_files = HashMap::new();
+/// A parsed game file.
+#[derive(Debug, Clone)]
+pub struct ParsedFile {
+    pub pairs: Vec<ConfigPair>,
+    /// Encoding the file was read in, and should be written back in.
+    pub encoding: Encoding,
+    /// Problems found while parsing. The pairs are whatever could be recovered.
+    pub diagnostics: Vec<Diagnostic>,
+    /// Index of the [`Layer`] the file was loaded from, 0 for the game.
+    pub layer: usize,
+}
+
+/// The files of a game, with a report on how loading each of them went.
+#[derive(Debug, Clone, Default)]
+pub struct Game {
+    /// Parsed files, keyed by their path relative to the game folder. Files without any
+    /// pairs or diagnostics are left out.
+    pub files: HashMap<String, ParsedFile>,
+    pub report: LoadReport,
+    /// The game and the mods it was loaded with, in load order.
+    pub layers: Vec<Layer>,
+    /// The texts of the localisation files of every layer.
+    pub localisation: Localisation,
+}
 
-    let files = find_txt_files(&path);
+impl Game {
+    /// Where the file with the relative path `file` is on disk.
+    pub fn path(&self, file: &str) -> Option<PathBuf> {
+        let layer = &self.layers[self.files.get(file)?.layer];
+        Some(layer.root.join(file))
+    }
+}
 
-    for file in files {
-        let file_name = file.file_stem().unwrap().to_str().unwrap().to_string();
-        let parsed = parse_file(&file);
+/// How far [`parse_game_with_progress`] has got.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// Files parsed so far.
+    pub done: usize,
+    pub total: usize,
+    /// Relative path of the file that was parsed last.
+    pub current: String,
+    /// Files parsed so far that could not be read or have at least one error.
+    pub errors: usize,
+}
 
-        if parsed.is_empty() {
-            continue;
+/// Parses every file under `path`, keyed by their path relative to it, such as
+/// `common/ideas/00_ideas.txt`.
+pub fn parse_game(path: &Path) -> Game {
+    parse_layers(&[Layer::game(path)], &Profile::default())
+}
+
+/// Like [`parse_game`], but parses files on all cores and calls `on_progress` after each
+/// file. Returns `None` when `cancel` gets set before every file is parsed.
+pub fn parse_game_with_progress(
+    path: &Path,
+    cancel: &AtomicBool,
+    on_progress: impl Fn(Progress) + Sync,
+) -> Option<Game> {
+    parse_layers_with_progress(
+        &[Layer::game(path)],
+        &Profile::default(),
+        cancel,
+        on_progress,
+    )
+}
+
+/// Parses the game and mods in `layers`, the game first, with files of later layers
+/// replacing those of earlier ones. See [`resolve_files`]. Uses of script features the
+/// game of `profile` does not understand are reported as warnings.
+pub fn parse_layers(layers: &[Layer], profile: &Profile) -> Game {
+    parse_layers_with_progress(layers, profile, &AtomicBool::new(false), |_| {})
+        .expect("loading is never cancelled")
+}
+
+/// Like [`parse_layers`], but with progress like [`parse_game_with_progress`].
+pub fn parse_layers_with_progress(
+    layers: &[Layer],
+    profile: &Profile,
+    cancel: &AtomicBool,
+    on_progress: impl Fn(Progress) + Sync,
+) -> Option<Game> {
+    let (files, unreadable) = resolve_files(layers, profile);
+    let (localisation_files, unreadable_localisation) = localisation_files(layers);
+    let total = files.len() + localisation_files.len();
+    let done = AtomicUsize::new(0);
+    let errors = AtomicUsize::new(unreadable.len() + unreadable_localisation.len());
+    let parsed_files = Mutex::new(HashMap::new());
+    let reports = Mutex::new(
+        unreadable
+            .iter()
+            .map(|(layer, folder, e)| {
+                FileReport::unreadable(relative_path(&layers[*layer].root, folder), e)
+            })
+            .collect::<Vec<_>>(),
+    );
+    let report_progress = |report: &FileReport| {
+        let errors = if matches!(report.status, FileStatus::Errors | FileStatus::Unreadable) {
+            errors.fetch_add(1, Ordering::Relaxed) + 1
+        } else {
+            errors.load(Ordering::Relaxed)
+        };
+
+        on_progress(Progress {
+            done: done.fetch_add(1, Ordering::Relaxed) + 1,
+            total,
+            current: report.path.clone(),
+            errors,
+        });
+    };
+
+    files.par_iter().try_for_each(|file| {
+        if cancel.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        let file_name = file.name.clone();
+        let start = Instant::now();
+        let (parsed, report) = match parse_file(&file.path, profile) {
+            Ok(mut parsed) => {
+                parsed.layer = file.layer;
+                let report = FileReport::new(
+                    file_name.clone(),
+                    start.elapsed(),
+                    parsed.pairs.len(),
+                    parsed.diagnostics.clone(),
+                );
+                (Some(parsed), report)
+            }
+            Err(e) => (None, FileReport::unreadable(file_name.clone(), &e)),
+        };
+
+        report_progress(&report);
+        if let Some(parsed) = parsed {
+            if !parsed.pairs.is_empty() || !parsed.diagnostics.is_empty() {
+                parsed_files.lock().unwrap().insert(file_name, parsed);
+            }
         }
+        reports.lock().unwrap().push(report);
 
-        parsed_files.insert(file_name.clone(), parsed);
+        Some(())
+    })?;
+
+    if cancel.load(Ordering::Relaxed) {
+        return None;
     }
+    let (localisation, localisation_reports) = load_localisation_files(
+        layers,
+        &localisation_files,
+        &unreadable_localisation,
+        cancel,
+        report_progress,
+    )?;
+    let mut reports = reports.into_inner().unwrap();
+    reports.extend(localisation_reports);
 
-    parsed_files
+    Some(Game {
+        files: parsed_files.into_inner().unwrap(),
+        report: LoadReport::new(reports),
+        layers: layers.to_vec(),
+        localisation,
+    })
 }
 
-fn find_txt_files(path: &PathBuf) -> Vec<PathBuf> {
+/// `file` relative to `root`, with `/` between folders on every platform. `root` itself
+/// is `.`.
+pub fn relative_path(root: &Path, file: &Path) -> String {
+    let path = file
+        .strip_prefix(root)
+        .unwrap_or(file)
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+
+    if path.is_empty() {
+        ".".to_owned()
+    } else {
+        path
+    }
+}
+
+/// Finds every `.txt` file under `path`. Folders that cannot be read are returned
+/// separately with their error, and skipped.
+pub fn find_txt_files(path: &PathBuf) -> (Vec<PathBuf>, Vec<(PathBuf, io::Error)>) {
+    let mut extensions = Extensions::new();
+    extensions.insert("txt", FileCategory::Script);
+
+    find_files(path, &extensions)
+}
+
+/// Like [`find_txt_files`], but finds files with any of `extensions`.
+pub fn find_files(
+    path: &PathBuf,
+    extensions: &Extensions,
+) -> (Vec<PathBuf>, Vec<(PathBuf, io::Error)>) {
     let mut files = Vec::new();
+    let mut errors = Vec::new();
+    find_files_into(path, extensions, &mut files, &mut errors);
 
-    for entry in fs::read_dir(path).unwrap() {
-        let entry = entry.unwrap();
-        let path = entry.path();
+    (files, errors)
+}
 
-        if path.is_dir() {
-            files.append(&mut find_txt_files(&path));
-        } else {
-            let extension = match path.extension() {
-                Some(extension) => extension,
-                None => continue,
-            };
+fn find_files_into(
+    path: &PathBuf,
+    extensions: &Extensions,
+    files: &mut Vec<PathBuf>,
+    errors: &mut Vec<(PathBuf, io::Error)>,
+) {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            errors.push((path.clone(), e));
+            return;
+        }
+    };
 
-            if extension != "txt" {
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                errors.push((path.clone(), e));
                 continue;
             }
+        };
+        let path = entry.path();
 
+        if path.is_dir() {
+            find_files_into(&path, extensions, files, errors);
+        } else if extensions.category(&path).is_some() {
             files.push(path);
         }
     }
+}
+
+/// Reads and parses the file at `path`, warning about what the game of `profile` reads
+/// differently: text in another encoding, and script features it does not understand.
+pub fn parse_file(path: &PathBuf, profile: &Profile) -> io::Result<ParsedFile> {
+    let unparsed = read_file(path)?;
+    let mut parsed = parse_config_file_recovering(&unparsed.text);
+    parsed.diagnostics.extend(profile.check_encoding(&unparsed));
+    parsed
+        .diagnostics
+        .extend(profile.dialect.check(&parsed.pairs));
 
-    files
+    Ok(ParsedFile {
+        pairs: parsed.pairs,
+        encoding: unparsed.encoding,
+        diagnostics: parsed.diagnostics,
+        layer: 0,
+    })
 }
 
-fn parse_file(path: &PathBuf) -> Vec<ConfigPair> {
-    let unparsed = read_file(path);
-    let parsed = parse_config_file(&unparsed);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::GameId;
+    use crate::test_util::TempDir;
 
-    match parsed {
-        Ok(parsed) => parsed,
-        Err(e) => {
-            eprintln!(
-                "Error parsing {}: {}",
-                path.file_stem().unwrap().to_str().unwrap(),
-                e
-            );
-            Vec::new()
-        }
+    #[test]
+    fn test_parse_game_same_file_names() {
+        let dir = TempDir::new("parse-game");
+        let root = dir.path();
+        fs::create_dir_all(root.join("common/ideas")).unwrap();
+        fs::create_dir_all(root.join("history/countries")).unwrap();
+        fs::write(root.join("common/ideas/00_ideas.txt"), "a = 1\n").unwrap();
+        fs::write(root.join("history/countries/00_ideas.txt"), "b = 2\n").unwrap();
+
+        let game = parse_game(root);
+
+        let mut files: Vec<&String> = game.files.keys().collect();
+        files.sort();
+        assert_eq!(
+            files,
+            [
+                "common/ideas/00_ideas.txt",
+                "history/countries/00_ideas.txt"
+            ]
+        );
+        assert_eq!(
+            game.files["common/ideas/00_ideas.txt"].pairs[0].identifier,
+            "a"
+        );
+        assert_eq!(
+            game.files["history/countries/00_ideas.txt"].pairs[0].identifier,
+            "b"
+        );
+    }
+
+    #[test]
+    fn test_parse_game_with_progress() {
+        let dir = TempDir::new("parse-game-progress");
+        let root = dir.path();
+        fs::create_dir_all(root.join("common")).unwrap();
+        fs::write(root.join("common/a.txt"), "a = 1\n").unwrap();
+        fs::write(root.join("common/b.txt"), "b = { 2\n").unwrap();
+        fs::write(root.join("common/c.txt"), "").unwrap();
+        fs::create_dir_all(root.join("localisation")).unwrap();
+        fs::write(
+            root.join("localisation/a_l_english.yml"),
+            "l_english:\n A:0 \"a\"\n",
+        )
+        .unwrap();
+
+        let progress = Mutex::new(Vec::new());
+        let game = parse_game_with_progress(root, &AtomicBool::new(false), |p| {
+            progress.lock().unwrap().push(p)
+        })
+        .unwrap();
+        let cancelled = parse_game_with_progress(root, &AtomicBool::new(true), |_| {});
+
+        let progress = progress.into_inner().unwrap();
+        assert_eq!(game.files.len(), 2);
+        assert_eq!(game.report.files.len(), 4);
+        assert_eq!(game.report.problems()[0].path, "common/b.txt");
+        assert_eq!(game.localisation.get("english", "A"), Some("a"));
+        assert_eq!(progress.len(), 4);
+        assert!(progress.iter().all(|p| p.total == 4));
+        assert_eq!(progress.iter().map(|p| p.done).max(), Some(4));
+        assert_eq!(
+            progress.last().map(|p| p.current.as_str()),
+            Some("localisation/a_l_english.yml")
+        );
+        assert_eq!(progress.iter().map(|p| p.errors).max(), Some(1));
+        assert!(cancelled.is_none());
+    }
+
+    #[test]
+    fn test_parse_layers_profile() {
+        let dir = TempDir::new("parse-layers-profile");
+        let root = dir.path();
+        fs::create_dir_all(root.join("common")).unwrap();
+        fs::create_dir_all(root.join("tests")).unwrap();
+        fs::write(root.join("common/a.txt"), "a ?= 1\n").unwrap();
+        fs::write(root.join("common/b.TXT"), "b = 1\n").unwrap();
+        fs::write(root.join("common/b.yml"), "l_english:\n").unwrap();
+        fs::write(root.join("tests/c.txt"), "c = 1\n").unwrap();
+        fs::create_dir_all(root.join("interface")).unwrap();
+        fs::write(root.join("interface/d.gui"), "guiTypes = { }\n").unwrap();
+        fs::write(root.join("descriptor.mod"), "name = \"Mod\"\n").unwrap();
+        fs::write(root.join("ThirdPartyLicenses.txt"), "MIT License\n").unwrap();
+
+        let profile = Profile::new(GameId::Eu4);
+        let game = parse_layers(&[Layer::game(root)], &profile);
+
+        let mut files: Vec<&String> = game.files.keys().collect();
+        files.sort();
+        assert_eq!(files, ["common/a.txt", "common/b.TXT", "interface/d.gui"]);
+        assert_eq!(game.files["common/a.txt"].diagnostics.len(), 1);
+        assert_eq!(game.report.count(FileStatus::Warnings), 1);
+    }
+
+    #[test]
+    fn test_parse_game_unreadable() {
+        let dir = TempDir::new("parse-game-unreadable");
+        let root = dir.path();
+        fs::create_dir_all(root.join("common")).unwrap();
+        fs::write(root.join("common/a.txt"), "a = 1\n").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(root.join("missing.txt"), root.join("common/b.txt")).unwrap();
+
+        let game = parse_game(root);
+        let missing = parse_game(&root.join("missing"));
+
+        assert_eq!(game.files.len(), 1);
+        #[cfg(unix)]
+        assert_eq!(game.report.problems()[0].path, "common/b.txt");
+        assert!(game
+            .report
+            .problems()
+            .iter()
+            .all(|file| file.status == FileStatus::Unreadable));
+
+        assert!(missing.files.is_empty());
+        assert_eq!(missing.report.files[0].path, ".");
+        assert_eq!(missing.report.files[0].status, FileStatus::Unreadable);
     }
 }
@@ -1,34 +1,185 @@
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
+use iced::futures::channel::mpsc;
+use iced::futures::Stream;
 use iced::widget::{
-    button, column, combo_box, container, horizontal_space, row, scrollable, text, vertical_space,
-    Column, Row,
+    button, checkbox, column, combo_box, container, horizontal_space, pick_list, progress_bar,
+    responsive, row, scrollable, text, text_input, vertical_space, Column, Row,
 };
-use iced::{Alignment, Command, Element, Length};
+use iced::{theme, Alignment, Color, Command, Element, Length};
 use uuid::Uuid;
 
-use crate::game::parse_game;
-use crate::parser::{ConfigPair, ConfigValue};
+use super::Error;
+
+use clausewitz_viewer::cst::{parse_syntax_tree, SyntaxTree};
+use clausewitz_viewer::edit::{
+    apply, parse_key, parse_pair, parse_sign, parse_value, Edit, EditError,
+};
+use clausewitz_viewer::export::{export, ExportFormat};
+use clausewitz_viewer::file::{encode, read_file, Encoding};
+use clausewitz_viewer::game::{parse_layers_with_progress, Game, ParsedFile, Progress};
+use clausewitz_viewer::history::History;
+use clausewitz_viewer::localisation::Localisation;
+use clausewitz_viewer::markup::{self, render, Markup, SegmentKind};
+use clausewitz_viewer::mods::{Definitions, Layer};
+use clausewitz_viewer::parser::{parse_config_file_recovering, ConfigPair, ConfigValue, Span};
+use clausewitz_viewer::profile::{FileCategory, Profile};
+use clausewitz_viewer::query::{query_game, Query};
+use clausewitz_viewer::report::{FileStatus, LoadReport};
+use clausewitz_viewer::search::{search, MatchMode, SearchOptions, SearchResult, SearchTarget};
+use clausewitz_viewer::variables::{scripted_variables, VariableError, Variables};
+
+/// Height of a row in the tree. Every row has the same height, so the rows in view can be
+/// worked out from the scroll offset.
+const ROW_HEIGHT: f32 = 30.0;
+
+/// Search results listed at most, so huge result sets do not stall the view.
+const MAX_SEARCH_RESULTS: usize = 500;
+
+/// Shown while unsaved edits keep anything else from loading, as reloading would lose them.
+const UNSAVED_EDITS: &str = "Save or undo the edits before loading anything else";
 
 #[derive(Debug, Clone)]
 pub enum Message {
     Selected(String),
-    Loaded(Arc<HashMap<String, Vec<ConfigPair>>>),
+    CategorySelected(CategoryFilter),
+    Progress(Progress),
+    Loaded(Arc<Game>),
+    CancelLoading,
+    LoadingCancelled,
     Collapse(String),
     Expand(String),
     CollapseAll,
     ExpandAll,
+    Export,
+    Exported(Result<PathBuf, Error>),
+    GroupDuplicates(bool),
+    ToggleErrors,
+    SearchChanged(String),
+    SearchTargetSelected(SearchTarget),
+    MatchModeSelected(MatchMode),
+    MatchCase(bool),
+    Search,
+    OpenSearchResult(usize),
+    FilterChanged(String),
+    QueryChanged(String),
+    QueryOpenFileOnly(bool),
+    RunQuery,
+    Scrolled(scrollable::Viewport),
+    StartEdit(Vec<usize>),
+    EditKey(String),
+    EditSign(String),
+    EditValue(String),
+    CommitEdit,
+    CancelEdit,
+    AddPair(Vec<usize>),
+    RemovePair(Vec<usize>),
+    MoveUp(Vec<usize>),
+    MoveDown(Vec<usize>),
+    Undo,
+    Redo,
+    Save,
+    Saved(Result<String, Error>),
+    AddMod,
+    AddModDescriptor,
+    ModPicked(Result<PathBuf, Error>),
+    RemoveMod(usize),
+    LanguageSelected(String),
 }
 
 #[derive(Debug)]
 pub struct DataView {
+    /// The game and the mods loaded on top of it, in load order.
+    layers: Vec<Layer>,
+    /// The detected game.
+    profile: Profile,
+    /// Top level definitions of every file, to tell which ones are overridden.
+    definitions: Definitions,
     is_loading: bool,
-    data: HashMap<String, Vec<ConfigPair>>,
-    current_open_file: HashMap<String, Vec<DataValue>>,
+    progress: Progress,
+    /// Set to stop the files still being loaded.
+    cancel: Arc<AtomicBool>,
+    is_cancelled: bool,
+    data: HashMap<String, ParsedFile>,
+    report: LoadReport,
+    show_errors: bool,
+    current_open_file: Vec<DataValue>,
+    /// Depth and position in `current_open_file` of every row shown, in order. Rebuilt
+    /// only when rows open or close, the filter changes or another file is opened.
+    visible_rows: Vec<(usize, Vec<usize>)>,
     files: combo_box::State<String>,
+    category: CategoryFilter,
     selected_file: Option<String>,
+    export_status: Option<String>,
+    group_duplicates: bool,
+    search_query: String,
+    search_options: SearchOptions,
+    search_results: Vec<SearchResult>,
+    search_error: Option<String>,
+    query: String,
+    query_open_file_only: bool,
+    tree: scrollable::Id,
+    /// Vertical scroll offset of the tree, in pixels.
+    scroll_offset: f32,
+    filter: String,
+    /// Rows matching `filter`.
+    filter_matches: usize,
+    /// Start of the span of the pair to highlight, such as an opened search result.
+    highlighted: Option<usize>,
+    /// Syntax trees of the files with unsaved edits.
+    edited: HashMap<String, SyntaxTree>,
+    /// Files edited since they were last saved.
+    unsaved: HashSet<String>,
+    /// Undo and redo stacks of every edited file.
+    history: HashMap<String, History>,
+    editing: Option<EditState>,
+    edit_status: Option<String>,
+    localisation: Localisation,
+    /// Language of the localised names shown next to keys and values.
+    language: String,
+    /// Scripted variables every file can use.
+    variables: Variables<'static>,
+}
+
+/// The category of files to offer in the file picker. `None` offers every file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CategoryFilter(Option<FileCategory>);
+
+impl CategoryFilter {
+    const ALL: [CategoryFilter; 6] = [
+        CategoryFilter(None),
+        CategoryFilter(Some(FileCategory::Script)),
+        CategoryFilter(Some(FileCategory::Interface)),
+        CategoryFilter(Some(FileCategory::Graphics)),
+        CategoryFilter(Some(FileCategory::Sound)),
+        CategoryFilter(Some(FileCategory::Mods)),
+    ];
+}
+
+impl Display for CategoryFilter {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.0 {
+            Some(category) => write!(f, "{}", category),
+            None => write!(f, "All files"),
+        }
+    }
+}
+
+/// The pair being edited, with the text typed so far.
+#[derive(Debug, Clone)]
+struct EditState {
+    path: Vec<usize>,
+    key: String,
+    sign: String,
+    /// `None` for blocks, whose contents are edited pair by pair.
+    value: Option<String>,
+    /// `Some` when the pair was inserted for this edit, so cancelling removes it again.
+    /// Holds whether the file had unsaved edits before the pair was inserted.
+    added: Option<bool>,
 }
 
 #[derive(Debug, Clone)]
@@ -37,21 +188,68 @@ struct DataValue {
     identifier: String,
     sign: String,
     value: String,
+    span: Span,
+    /// Index path of the pair, as used by [`Edit`]. `None` for rows grouping duplicates.
+    path: Option<Vec<usize>>,
+    /// Whether the value is a block of pairs.
+    is_block: bool,
+    /// Where the definition used instead of this top level pair is, if any.
+    overridden_by: Option<String>,
+    /// Localised text of the key, or else of the value, in the selected language.
+    localised: Option<Markup>,
+    /// What the value stands for, if it is a scripted variable or inline math.
+    resolved: Option<Result<String, VariableError>>,
     open: bool,
+    /// Whether the row passes the filter, or is needed to show a row that does.
+    visible: bool,
     children: Vec<DataValue>,
 }
 
 impl DataView {
-    pub fn new(path: PathBuf) -> (Self, iced::Command<Message>) {
+    /// Loads the game and mods in `layers`, the game first, as the game of `profile`.
+    pub fn new(layers: Vec<Layer>, profile: Profile) -> (Self, iced::Command<Message>) {
+        let cancel = Arc::new(AtomicBool::new(false));
+
         (
             DataView {
+                layers: layers.clone(),
+                profile: profile.clone(),
+                definitions: Definitions::default(),
                 is_loading: true,
+                progress: Progress::default(),
+                cancel: cancel.clone(),
+                is_cancelled: false,
                 data: HashMap::new(),
-                current_open_file: HashMap::new(),
+                report: LoadReport::default(),
+                show_errors: false,
+                current_open_file: Vec::new(),
+                visible_rows: Vec::new(),
                 files: combo_box::State::new(vec![]),
+                category: CategoryFilter(None),
                 selected_file: None,
+                export_status: None,
+                group_duplicates: false,
+                search_query: String::new(),
+                search_options: SearchOptions::default(),
+                search_results: Vec::new(),
+                search_error: None,
+                query: String::new(),
+                query_open_file_only: false,
+                tree: scrollable::Id::unique(),
+                scroll_offset: 0.0,
+                filter: String::new(),
+                filter_matches: 0,
+                highlighted: None,
+                edited: HashMap::new(),
+                unsaved: HashSet::new(),
+                history: HashMap::new(),
+                editing: None,
+                edit_status: None,
+                localisation: Localisation::default(),
+                language: String::new(),
+                variables: Variables::default(),
             },
-            Command::perform(parse(path.clone()), Message::Loaded),
+            Command::run(load(layers, profile, cancel), |message| message),
         )
     }
 
@@ -67,32 +265,411 @@ impl DataView {
         }
 
         match message {
-            Message::Loaded(data) => {
+            Message::Progress(progress) => {
+                self.progress = progress;
+
+                Command::none()
+            }
+            Message::CancelLoading => {
+                self.cancel.store(true, Ordering::Relaxed);
+
+                Command::none()
+            }
+            Message::LoadingCancelled => {
                 self.is_loading = false;
-                self.data = (*data).clone();
-                self.files = combo_box::State::new(self.data.keys().cloned().collect());
+                self.is_cancelled = true;
+
+                Command::none()
+            }
+            Message::Loaded(game) => {
+                let game = Arc::unwrap_or_clone(game);
+                self.is_loading = false;
+                self.data = game.files;
+                self.report = game.report;
+                self.localisation = game.localisation;
+                self.language = if self
+                    .localisation
+                    .languages()
+                    .any(|language| language == "english")
+                {
+                    "english".to_owned()
+                } else {
+                    self.localisation
+                        .languages()
+                        .next()
+                        .unwrap_or_default()
+                        .to_owned()
+                };
+                self.definitions = Definitions::new(&self.data, &self.profile.merge_rules);
+                self.variables = scripted_variables(&self.data);
+                self.update_files();
+
+                Command::none()
+            }
+            Message::LanguageSelected(language) => {
+                self.language = language;
+                self.refresh();
+
+                Command::none()
+            }
+            Message::CategorySelected(category) => {
+                self.category = category;
+                self.update_files();
 
                 Command::none()
             }
             Message::Selected(file) => {
-                self.selected_file = Some(file.clone());
-
-                let data = self.data.get(&file).unwrap();
-                self.current_open_file = HashMap::new();
-
-                for pair in data {
-                    let value = map_values(pair);
-
-                    if !self.current_open_file.contains_key(&pair.identifier) {
-                        self.current_open_file
-                            .insert(pair.identifier.clone(), vec![value]);
-                    } else {
-                        self.current_open_file
-                            .get_mut(&pair.identifier)
-                            .unwrap()
-                            .push(value);
+                if !self.data.contains_key(&file) {
+                    return Command::none();
+                }
+                self.leave_edit();
+                self.current_open_file = self.rows(&file);
+                self.filter_matches = filter_values(&mut self.current_open_file, &self.filter);
+                self.update_visible_rows();
+                self.selected_file = Some(file);
+                self.highlighted = None;
+                self.edit_status = None;
+
+                self.scroll_to(0)
+            }
+            Message::SearchChanged(query) => {
+                self.search_query = query;
+
+                Command::none()
+            }
+            Message::SearchTargetSelected(target) => {
+                self.search_options.target = target;
+
+                Command::none()
+            }
+            Message::MatchModeSelected(mode) => {
+                self.search_options.mode = mode;
+
+                Command::none()
+            }
+            Message::MatchCase(case_sensitive) => {
+                self.search_options.case_sensitive = case_sensitive;
+
+                Command::none()
+            }
+            Message::Search => {
+                match search(&self.data, &self.search_query, self.search_options) {
+                    Ok(results) => {
+                        self.search_results = results;
+                        self.search_error = None;
+                    }
+                    Err(e) => {
+                        self.search_results.clear();
+                        self.search_error = Some(e.to_string());
+                    }
+                }
+
+                Command::none()
+            }
+            Message::StartEdit(path) => {
+                if let Some(value) = find_value(&self.current_open_file, &path) {
+                    self.editing = Some(EditState {
+                        key: value.identifier.clone(),
+                        sign: value.sign.clone(),
+                        value: (!value.is_block).then(|| value.value.clone()),
+                        path,
+                        added: None,
+                    });
+                    self.edit_status = None;
+                }
+
+                Command::none()
+            }
+            Message::EditKey(key) => {
+                if let Some(editing) = &mut self.editing {
+                    editing.key = key;
+                }
+
+                Command::none()
+            }
+            Message::EditSign(sign) => {
+                if let Some(editing) = &mut self.editing {
+                    editing.sign = sign;
+                }
+
+                Command::none()
+            }
+            Message::EditValue(value) => {
+                if let Some(editing) = &mut self.editing {
+                    editing.value = Some(value);
+                }
+
+                Command::none()
+            }
+            Message::CommitEdit => {
+                let Some(editing) = self.editing.clone() else {
+                    return Command::none();
+                };
+
+                match self.commit_edit(&editing) {
+                    Ok(_) => {
+                        self.editing = None;
+                        self.edit_status = None;
+                    }
+                    Err(e) => self.edit_status = Some(e),
+                }
+
+                Command::none()
+            }
+            Message::CancelEdit => {
+                self.leave_edit();
+                self.edit_status = None;
+
+                Command::none()
+            }
+            Message::AddPair(parent) => {
+                let Some(file) = &self.selected_file else {
+                    return Command::none();
+                };
+                let Some(siblings) = pairs_at(&self.data[file].pairs, &parent) else {
+                    return Command::none();
+                };
+
+                let mut path = parent.clone();
+                path.push(siblings.len());
+                let was_unsaved = self.unsaved.contains(file);
+                let edit = Edit::Insert {
+                    path: path.clone(),
+                    pair: parse_pair("key = value").unwrap(),
+                    comment: None,
+                };
+
+                match self.edit(&[edit]) {
+                    Ok(_) => {
+                        self.open_path(&parent);
+                        self.editing = Some(EditState {
+                            path,
+                            key: "key".to_owned(),
+                            sign: "=".to_owned(),
+                            value: Some("value".to_owned()),
+                            added: Some(was_unsaved),
+                        });
+                        self.edit_status = None;
+                    }
+                    Err(e) => self.edit_status = Some(e),
+                }
+
+                Command::none()
+            }
+            Message::RemovePair(path) => {
+                if self
+                    .leave_edit()
+                    .is_some_and(|added| path.starts_with(&added))
+                {
+                    return Command::none();
+                }
+                self.edit_status = self.edit(&[Edit::Remove { path }]).err();
+
+                Command::none()
+            }
+            Message::MoveUp(from) => {
+                let Some((&index, parent)) = from.split_last() else {
+                    return Command::none();
+                };
+                if index == 0 {
+                    return Command::none();
+                }
+
+                if self
+                    .leave_edit()
+                    .is_some_and(|added| from.starts_with(&added))
+                {
+                    return Command::none();
+                }
+                let mut to = parent.to_vec();
+                to.push(index - 1);
+                self.edit_status = self.edit(&[Edit::Move { from, to }]).err();
+
+                Command::none()
+            }
+            Message::MoveDown(from) => {
+                if self
+                    .leave_edit()
+                    .is_some_and(|added| from.starts_with(&added))
+                {
+                    return Command::none();
+                }
+                let Some(file) = &self.selected_file else {
+                    return Command::none();
+                };
+                let Some((&index, parent)) = from.split_last() else {
+                    return Command::none();
+                };
+                let siblings = pairs_at(&self.data[file].pairs, parent).map_or(0, <[_]>::len);
+                if index + 1 >= siblings {
+                    return Command::none();
+                }
+
+                let mut to = parent.to_vec();
+                to.push(index + 1);
+                self.edit_status = self.edit(&[Edit::Move { from, to }]).err();
+
+                Command::none()
+            }
+            Message::Undo => {
+                self.step_history(true);
+
+                Command::none()
+            }
+            Message::Redo => {
+                self.step_history(false);
+
+                Command::none()
+            }
+            Message::Save => {
+                self.leave_edit();
+                let Some(file) = &self.selected_file else {
+                    return Command::none();
+                };
+                let Some(tree) = self.edited.get(file) else {
+                    return Command::none();
+                };
+
+                Command::perform(
+                    save_file(
+                        self.file_path(file),
+                        file.clone(),
+                        tree.to_string(),
+                        self.data[file].encoding,
+                    ),
+                    Message::Saved,
+                )
+            }
+            Message::Saved(result) => {
+                self.edit_status = match result {
+                    Ok(file) => {
+                        self.unsaved.remove(&file);
+                        Some(format!("Saved {}", file))
+                    }
+                    Err(Error::DialogClosed) => None,
+                    Err(Error::Io(e)) => Some(format!("Saving failed: {}", e)),
+                };
+
+                Command::none()
+            }
+            Message::AddMod => Command::perform(pick_mod(false), Message::ModPicked),
+            Message::AddModDescriptor => Command::perform(pick_mod(true), Message::ModPicked),
+            Message::ModPicked(result) => {
+                let Ok(path) = result else {
+                    return Command::none();
+                };
+
+                match Layer::from_mod(&path) {
+                    Ok(layer) => {
+                        let mut layers = self.layers.clone();
+                        layers.push(layer);
+                        self.reload(layers)
+                    }
+                    Err(e) => {
+                        self.edit_status = Some(format!("{}: {}", path.display(), e));
+                        Command::none()
                     }
                 }
+            }
+            Message::RemoveMod(index) => {
+                let mut layers = self.layers.clone();
+                layers.remove(index);
+                self.reload(layers)
+            }
+            Message::Scrolled(viewport) => {
+                self.scroll_offset = viewport.absolute_offset().y;
+
+                Command::none()
+            }
+            Message::FilterChanged(filter) => {
+                self.filter_matches = filter_values(&mut self.current_open_file, &filter);
+                self.filter = filter;
+                self.update_visible_rows();
+
+                self.scroll_to(0)
+            }
+            Message::QueryChanged(query) => {
+                self.query = query;
+
+                Command::none()
+            }
+            Message::QueryOpenFileOnly(open_file_only) => {
+                self.query_open_file_only = open_file_only;
+
+                Command::none()
+            }
+            Message::RunQuery => {
+                let query = match Query::parse(&self.query) {
+                    Ok(query) => query,
+                    Err(e) => {
+                        self.search_results.clear();
+                        self.search_error = Some(e.to_string());
+                        return Command::none();
+                    }
+                };
+
+                let matches = match &self.selected_file {
+                    Some(file) if self.query_open_file_only => query
+                        .select(&self.data[file].pairs)
+                        .into_iter()
+                        .map(|found| (file.as_str(), found))
+                        .collect(),
+                    _ => query_game(&self.data, &query),
+                };
+
+                self.search_results = matches
+                    .into_iter()
+                    .map(|(file, found)| SearchResult {
+                        file: file.to_owned(),
+                        value: match found.pair.value {
+                            ConfigValue::Object(_) => String::new(),
+                            ref value => value.to_string(),
+                        },
+                        path: found.path,
+                        span: found.pair.span,
+                    })
+                    .collect();
+                self.search_error = None;
+
+                Command::none()
+            }
+            Message::OpenSearchResult(index) => {
+                let result = self.search_results[index].clone();
+                if !self.data.contains_key(&result.file) {
+                    return Command::none();
+                }
+
+                self.current_open_file = self.rows(&result.file);
+                self.filter_matches = filter_values(&mut self.current_open_file, &self.filter);
+                for value in self.current_open_file.iter_mut() {
+                    expand_to(value, result.span.start);
+                }
+                self.selected_file = Some(result.file);
+                self.highlighted = Some(result.span.start);
+                self.update_visible_rows();
+
+                let row = self
+                    .visible_rows
+                    .iter()
+                    .position(|(_, position)| {
+                        row_at(&self.current_open_file, position).span.start == result.span.start
+                    })
+                    .unwrap_or_default();
+                self.scroll_to(row)
+            }
+            Message::ToggleErrors => {
+                self.show_errors = !self.show_errors;
+
+                Command::none()
+            }
+            Message::GroupDuplicates(group_duplicates) => {
+                self.group_duplicates = group_duplicates;
+
+                if let Some(file) = &self.selected_file {
+                    self.current_open_file = self.rows(file);
+                    self.filter_matches = filter_values(&mut self.current_open_file, &self.filter);
+                    self.update_visible_rows();
+                }
 
                 Command::none()
             }
@@ -105,11 +682,10 @@ impl DataView {
                     }
                 }
 
-                for value in self.current_open_file.values_mut() {
-                    for val in value {
-                        traverse(val, &item, collapse);
-                    }
+                for value in self.current_open_file.iter_mut() {
+                    traverse(value, &item, collapse);
                 }
+                self.update_visible_rows();
 
                 Command::none()
             }
@@ -118,11 +694,10 @@ impl DataView {
                     value.open = true;
                 }
 
-                for value in self.current_open_file.values_mut() {
-                    for val in value {
-                        traverse(val, &item, expand);
-                    }
+                for value in self.current_open_file.iter_mut() {
+                    traverse(value, &item, expand);
                 }
+                self.update_visible_rows();
 
                 Command::none()
             }
@@ -135,11 +710,29 @@ impl DataView {
                     }
                 }
 
-                for value in self.current_open_file.values_mut() {
-                    for val in value {
-                        collapse(val);
-                    }
+                for value in self.current_open_file.iter_mut() {
+                    collapse(value);
                 }
+                self.update_visible_rows();
+
+                Command::none()
+            }
+            Message::Export => {
+                let Some(file) = &self.selected_file else {
+                    return Command::none();
+                };
+
+                Command::perform(
+                    export_file(file.clone(), self.data[file].pairs.clone()),
+                    Message::Exported,
+                )
+            }
+            Message::Exported(result) => {
+                self.export_status = match result {
+                    Ok(path) => Some(format!("Exported to {}", path.display())),
+                    Err(Error::DialogClosed) => None,
+                    Err(Error::Io(e)) => Some(format!("Export failed: {}", e)),
+                };
 
                 Command::none()
             }
@@ -152,21 +745,291 @@ impl DataView {
                     }
                 }
 
-                for value in self.current_open_file.values_mut() {
-                    for val in value {
-                        expand(val);
-                    }
+                for value in self.current_open_file.iter_mut() {
+                    expand(value);
                 }
+                self.update_visible_rows();
 
                 Command::none()
             }
         }
     }
 
-    pub fn view(&self) -> Element<Message> {
-        if self.is_loading {
+    /// Turns the typed key, sign and value into edits, and applies them.
+    fn commit_edit(&mut self, editing: &EditState) -> Result<(), String> {
+        let value = find_value(&self.current_open_file, &editing.path)
+            .ok_or_else(|| "The pair no longer exists".to_owned())?;
+        let path = editing.path.clone();
+        let mut edits = Vec::new();
+
+        if editing.key != value.identifier {
+            let key = parse_key(&editing.key).map_err(|e| e.to_string())?;
+            edits.push(Edit::SetKey {
+                path: path.clone(),
+                key,
+            });
+        }
+        if editing.sign != value.sign {
+            let sign = parse_sign(&editing.sign).map_err(|e| e.to_string())?;
+            edits.push(Edit::SetSign {
+                path: path.clone(),
+                sign,
+            });
+        }
+        if let Some(text) = editing.value.as_ref().filter(|text| **text != value.value) {
+            let value = parse_value(text).map_err(|e| e.to_string())?;
+            edits.push(Edit::SetValue { path, value });
+        }
+
+        if edits.is_empty() {
+            return Ok(());
+        }
+        self.edit(&edits)
+    }
+
+    /// Applies `edits` to the open file and records them in its history.
+    fn edit(&mut self, edits: &[Edit]) -> Result<(), String> {
+        let undo = self.apply_edits(edits)?;
+
+        if let Some(file) = &self.selected_file {
+            self.history.entry(file.clone()).or_default().record(undo);
+        }
+        Ok(())
+    }
+
+    /// Stops editing without committing. A pair added for the edit by [`Message::AddPair`]
+    /// is removed again, and its path returned.
+    fn leave_edit(&mut self) -> Option<Vec<usize>> {
+        let editing = self.editing.take()?;
+        let was_unsaved = editing.added?;
+        self.discard_added_pair(was_unsaved);
+
+        Some(editing.path)
+    }
+
+    /// Removes the pair inserted by [`Message::AddPair`] when its edit is left, leaving no
+    /// trace of it in the history.
+    fn discard_added_pair(&mut self, was_unsaved: bool) {
+        let Some(file) = self.selected_file.clone() else {
+            return;
+        };
+        let Some(undo) = self.history.get_mut(&file).and_then(History::discard_last) else {
+            return;
+        };
+
+        match self.apply_edits(&undo) {
+            Ok(_) if !was_unsaved => {
+                self.unsaved.remove(&file);
+            }
+            Ok(_) => {}
+            Err(e) => self.edit_status = Some(e),
+        }
+    }
+
+    /// Undoes, or redoes, the last change to the open file.
+    fn step_history(&mut self, undo: bool) {
+        // Leaving an added pair undoes adding it.
+        if self.leave_edit().is_some() && undo {
+            return;
+        }
+        let Some(file) = self.selected_file.clone() else {
+            return;
+        };
+        let Some(mut history) = self.history.remove(&file) else {
+            return;
+        };
+
+        let result = if undo {
+            history.undo(|edits| self.apply_edits(edits))
+        } else {
+            history.redo(|edits| self.apply_edits(edits))
+        };
+        self.history.insert(file, history);
+
+        self.edit_status = result.and_then(Result::err);
+    }
+
+    /// Applies `edits` to the open file, in order. Nothing changes when one of them fails,
+    /// or when the file would no longer parse. Returns the edits that undo them, in the
+    /// order to apply them.
+    fn apply_edits(&mut self, edits: &[Edit]) -> Result<Vec<Edit>, String> {
+        let file = self
+            .selected_file
+            .clone()
+            .ok_or_else(|| "No file is open".to_owned())?;
+
+        let mut tree = match self.edited.get(&file) {
+            Some(tree) => tree.clone(),
+            None => {
+                let source = read_file(&self.file_path(&file)).map_err(|e| e.to_string())?;
+                parse_syntax_tree(&source.text)
+                    .map_err(|_| "The file has parse errors and cannot be edited".to_owned())?
+            }
+        };
+
+        let mut undo = edits
+            .iter()
+            .map(|edit| apply(&mut tree, edit))
+            .collect::<Result<Vec<Edit>, EditError>>()
+            .map_err(|e| e.to_string())?;
+        undo.reverse();
+
+        let parsed = parse_config_file_recovering(&tree.to_string());
+        if parsed.has_errors() {
+            return Err("The file would no longer parse".to_owned());
+        }
+
+        let data = self.data.get_mut(&file).unwrap();
+        data.pairs = parsed.pairs;
+        data.diagnostics = parsed.diagnostics;
+        self.edited.insert(file.clone(), tree);
+        self.unsaved.insert(file);
+        self.definitions = Definitions::new(&self.data, &self.profile.merge_rules);
+        self.variables = scripted_variables(&self.data);
+        self.refresh();
+
+        Ok(undo)
+    }
+
+    /// The rows of `file`, with their localised names, resolved variables and a note on
+    /// the top level pairs that are not used because a definition elsewhere is.
+    fn rows(&self, file: &str) -> Vec<DataValue> {
+        let pairs = &self.data[file].pairs;
+        let mut rows = map_pairs(pairs, self.group_duplicates, &[]);
+        localise(&mut rows, &self.localisation, &self.language);
+
+        resolve_variables(&mut rows, pairs, &self.variables);
+
+        let top_level = rows.iter_mut().flat_map(|value| match value.path {
+            Some(_) => std::slice::from_mut(value),
+            None => value.children.as_mut_slice(),
+        });
+        for value in top_level {
+            let Some([index]) = value.path.as_deref() else {
+                continue;
+            };
+
+            value.overridden_by = self
+                .definitions
+                .overridden_by(file, *index, &value.identifier)
+                .map(|definition| {
+                    format!(
+                        "overridden by {} ({})",
+                        definition.file, self.layers[definition.layer].name
+                    )
+                });
+        }
+
+        rows
+    }
+
+    /// Where `file` is on disk.
+    fn file_path(&self, file: &str) -> PathBuf {
+        self.layers[self.data[file].layer].root.join(file)
+    }
+
+    /// Rebuilds the rows of the open file after it changed, keeping open rows open.
+    fn refresh(&mut self) {
+        let Some(file) = &self.selected_file else {
+            return;
+        };
+
+        let mut open = HashSet::new();
+        open_paths(&self.current_open_file, &mut open);
+        self.current_open_file = self.rows(file);
+        reopen(&mut self.current_open_file, &open);
+        self.filter_matches = filter_values(&mut self.current_open_file, &self.filter);
+        self.update_visible_rows();
+    }
+
+    /// Opens the row of the pair at `path` and the rows leading to it.
+    fn open_path(&mut self, path: &[usize]) {
+        let mut open = HashSet::new();
+        for length in 1..=path.len() {
+            open.insert(path[..length].to_vec());
+        }
+        reopen(&mut self.current_open_file, &open);
+        self.update_visible_rows();
+    }
+
+    fn update_visible_rows(&mut self) {
+        self.visible_rows = visible_rows(&self.current_open_file);
+    }
+
+    /// Offers the files of the selected category in the file picker.
+    fn update_files(&mut self) {
+        let mut files: Vec<String> = self
+            .data
+            .keys()
+            .filter(|file| {
+                self.category.0.is_none()
+                    || self.profile.extensions.category(Path::new(file)) == self.category.0
+            })
+            .cloned()
+            .collect();
+        files.sort();
+
+        self.files = combo_box::State::new(files);
+    }
+
+    /// Whether the view can be replaced without losing unsaved edits. Says why not when it
+    /// cannot.
+    pub fn can_reload(&mut self) -> bool {
+        if self.unsaved.is_empty() {
+            return true;
+        }
+
+        self.edit_status = Some(UNSAVED_EDITS.to_owned());
+        false
+    }
+
+    /// Starts over, loading `layers` instead. Refused while there are unsaved edits, which
+    /// would be lost.
+    fn reload(&mut self, layers: Vec<Layer>) -> Command<Message> {
+        if !self.can_reload() {
+            return Command::none();
+        }
+
+        let (view, command) = DataView::new(layers, self.profile.clone());
+        *self = view;
+        command
+    }
+
+    /// Scrolls the tree so the row at `row` is at the top.
+    fn scroll_to(&mut self, row: usize) -> Command<Message> {
+        self.scroll_offset = row as f32 * ROW_HEIGHT;
+
+        scrollable::scroll_to(
+            self.tree.clone(),
+            scrollable::AbsoluteOffset {
+                x: 0.0,
+                y: self.scroll_offset,
+            },
+        )
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        if self.is_loading || self.is_cancelled {
+            let progress = &self.progress;
+            let status = if self.is_cancelled {
+                column![text("Loading cancelled").size(50)]
+            } else {
+                column![
+                    text(format!("Loading {}...", self.profile)).size(50),
+                    vertical_space().height(50),
+                    progress_bar(0.0..=progress.total.max(1) as f32, progress.done as f32)
+                        .width(600),
+                    text(format!(
+                        "{} / {} files, {} with errors",
+                        progress.done, progress.total, progress.errors
+                    )),
+                    text(&progress.current),
+                    button("Cancel").on_press(Message::CancelLoading),
+                ]
+            };
+
             return container(
-                column![text("Loading...").size(50), vertical_space().height(50),]
+                status
                     .width(Length::Fill)
                     .align_items(Alignment::Center)
                     .spacing(10),
@@ -184,23 +1047,37 @@ impl DataView {
             self.selected_file.as_ref(),
             Message::Selected,
         )
-        .width(450);
+        .width(600);
+
+        let encoding = match &self.selected_file {
+            Some(file) => text(format!(
+                "{}, from {}",
+                self.data[file].encoding, self.layers[self.data[file].layer].name
+            )),
+            None => text(""),
+        };
 
-        fn create_row(key: &str, value: &DataValue, depth: usize) -> Column<'static, Message> {
-            let mut col = Column::new();
-            let mut row = Row::new();
+        fn create_row(
+            value: &DataValue,
+            depth: usize,
+            highlighted: Option<usize>,
+            editing: Option<&EditState>,
+        ) -> Element<'static, Message> {
+            let mut row = Row::new().align_items(Alignment::Center);
             let button_width = 20;
             let indent_width = 25;
+            let line_width = 60;
 
+            row = row.push(text(value.span.line).width(line_width));
             row = row.push(horizontal_space().width((indent_width * depth) as u16));
 
-            if value.children.len() > 0 && !value.open {
+            if !value.children.is_empty() && !value.open {
                 row = row.push(
                     button("+")
                         .width(button_width)
                         .on_press(Message::Expand(value.id.clone())),
                 );
-            } else if value.children.len() > 0 && value.open {
+            } else if !value.children.is_empty() && value.open {
                 row = row.push(
                     button("-")
                         .width(button_width)
@@ -211,43 +1088,203 @@ impl DataView {
             }
 
             row = row.push(horizontal_space().width(10));
-            row = row.push(text(format!("{} {} {}", key, value.sign, value.value)));
 
-            col = col.push(row);
-            col = col.push(vertical_space().height(10));
+            if let Some(editing) =
+                editing.filter(|editing| Some(&editing.path) == value.path.as_ref())
+            {
+                row = row
+                    .push(
+                        text_input("key", &editing.key)
+                            .on_input(Message::EditKey)
+                            .on_submit(Message::CommitEdit)
+                            .width(200),
+                    )
+                    .push(
+                        text_input("=", &editing.sign)
+                            .on_input(Message::EditSign)
+                            .on_submit(Message::CommitEdit)
+                            .width(40),
+                    );
+                if let Some(value) = &editing.value {
+                    row = row.push(
+                        text_input("value", value)
+                            .on_input(Message::EditValue)
+                            .on_submit(Message::CommitEdit)
+                            .width(300),
+                    );
+                }
 
-            if value.open {
-                for child in value.children.iter() {
-                    col = col.push(create_row(&child.identifier, child, depth + 1));
+                return container(
+                    row.push(button("OK").on_press(Message::CommitEdit))
+                        .push(
+                            button("Cancel")
+                                .style(theme::Button::Secondary)
+                                .on_press(Message::CancelEdit),
+                        )
+                        .spacing(5),
+                )
+                .height(ROW_HEIGHT)
+                .into();
+            }
+
+            let label = text(if value.sign.is_empty() {
+                format!("{} {}", value.identifier, value.value)
+            } else {
+                format!("{} {} {}", value.identifier, value.sign, value.value)
+            });
+            row = row.push(if highlighted == Some(value.span.start) {
+                label.style(Color::from_rgb(1.0, 0.8, 0.2))
+            } else {
+                label
+            });
+            if let Some(resolved) = &value.resolved {
+                row = row.push(horizontal_space().width(10)).push(match resolved {
+                    Ok(resolved) => {
+                        text(format!("→ {}", resolved)).style(Color::from_rgb(0.6, 0.6, 0.6))
+                    }
+                    Err(e) => text(e.to_string())
+                        .size(12)
+                        .style(Color::from_rgb(0.9, 0.4, 0.4)),
+                });
+            }
+            if let Some(localised) = &value.localised {
+                row = row
+                    .push(horizontal_space().width(10))
+                    .push(markup_row(localised));
+                if !localised.unresolved.is_empty() {
+                    let unresolved: Vec<String> = localised
+                        .unresolved
+                        .iter()
+                        .map(|reference| reference.to_string())
+                        .collect();
+                    row = row.push(horizontal_space().width(10)).push(
+                        text(format!("unresolved: {}", unresolved.join(", ")))
+                            .size(12)
+                            .style(Color::from_rgb(0.6, 0.6, 0.6)),
+                    );
+                }
+            }
+            if let Some(overridden_by) = &value.overridden_by {
+                row = row.push(horizontal_space().width(10)).push(
+                    text(overridden_by)
+                        .size(12)
+                        .style(Color::from_rgb(0.6, 0.6, 0.6)),
+                );
+            }
+
+            if let Some(path) = &value.path {
+                row = row.push(horizontal_space().width(10)).push(
+                    button(text("Edit").size(12))
+                        .style(theme::Button::Text)
+                        .on_press(Message::StartEdit(path.clone())),
+                );
+                row = row
+                    .push(
+                        button(text("↑").size(12))
+                            .style(theme::Button::Text)
+                            .on_press(Message::MoveUp(path.clone())),
+                    )
+                    .push(
+                        button(text("↓").size(12))
+                            .style(theme::Button::Text)
+                            .on_press(Message::MoveDown(path.clone())),
+                    );
+                if value.is_block {
+                    row = row.push(
+                        button(text("+").size(12))
+                            .style(theme::Button::Text)
+                            .on_press(Message::AddPair(path.clone())),
+                    );
                 }
+                row = row.push(
+                    button(text("×").size(12))
+                        .style(theme::Button::Text)
+                        .on_press(Message::RemovePair(path.clone())),
+                );
             }
 
-            col
+            container(row).height(ROW_HEIGHT).into()
         }
 
-        let selected_file = if let Some(file) = &self.selected_file {
-            let mut content = Column::new();
-            for (key, value) in self.current_open_file.iter() {
-                if value.len() == 1 {
-                    content = content.push(create_row(key, &value[0], 0));
-                } else if value.len() > 1 {
-                    for val in value.iter() {
-                        content = content.push(create_row(key, val, 0));
-                    }
+        let selected_file = if self.selected_file.is_some() {
+            let rows = &self.visible_rows;
+            let (can_undo, can_redo) = self
+                .selected_file
+                .as_ref()
+                .and_then(|file| self.history.get(file))
+                .map_or((false, false), |history| {
+                    (history.can_undo(), history.can_redo())
+                });
+
+            // Only the rows in the viewport are built, with empty space standing in for the
+            // rows above and below, so the size of the file does not matter.
+            let content = responsive(move |size| {
+                let first = ((self.scroll_offset / ROW_HEIGHT) as usize).min(rows.len());
+                let last = (first + (size.height / ROW_HEIGHT).ceil() as usize + 1).min(rows.len());
+
+                let mut column =
+                    Column::new().push(vertical_space().height(first as f32 * ROW_HEIGHT));
+                for (depth, position) in &rows[first..last] {
+                    column = column.push(create_row(
+                        row_at(&self.current_open_file, position),
+                        *depth,
+                        self.highlighted,
+                        self.editing.as_ref(),
+                    ));
                 }
-            }
+                column =
+                    column.push(vertical_space().height((rows.len() - last) as f32 * ROW_HEIGHT));
+
+                scrollable(column.width(Length::Fill))
+                    .id(self.tree.clone())
+                    .on_scroll(Message::Scrolled)
+                    .height(Length::Fill)
+                    .into()
+            });
 
             container(
                 column![
                     row![
                         button("Expand all").on_press(Message::ExpandAll),
                         button("Collapse all").on_press(Message::CollapseAll),
+                        checkbox("Group duplicates", self.group_duplicates)
+                            .on_toggle(Message::GroupDuplicates),
+                        button("Export file...").on_press(Message::Export),
+                        text(self.export_status.as_deref().unwrap_or_default()),
                     ]
-                    .spacing(10),
+                    .spacing(10)
+                    .align_items(Alignment::Center),
+                    row![
+                        button("Add pair").on_press(Message::AddPair(Vec::new())),
+                        button("Undo").on_press_maybe(can_undo.then_some(Message::Undo)),
+                        button("Redo").on_press_maybe(can_redo.then_some(Message::Redo)),
+                        button("Save").on_press_maybe(
+                            self.selected_file
+                                .as_ref()
+                                .filter(|file| self.unsaved.contains(*file))
+                                .map(|_| Message::Save)
+                        ),
+                        text(self.edit_status.as_deref().unwrap_or_default()),
+                    ]
+                    .spacing(10)
+                    .align_items(Alignment::Center),
+                    row![
+                        text_input("Filter...", &self.filter)
+                            .on_input(Message::FilterChanged)
+                            .width(400),
+                        text(if self.filter.is_empty() {
+                            String::new()
+                        } else {
+                            format!("{} matches", self.filter_matches)
+                        }),
+                    ]
+                    .spacing(10)
+                    .align_items(Alignment::Center),
                     content
                 ]
                 .spacing(10)
-                .width(Length::Fill),
+                .width(Length::Fill)
+                .height(Length::Fill),
             )
         } else {
             container(
@@ -258,14 +1295,181 @@ impl DataView {
             )
         };
 
-        let content = column![
+        let problems = self.report.problems();
+        let errors_button =
+            button(text(format!("Errors ({})", problems.len()))).on_press(Message::ToggleErrors);
+
+        let mut load_order = Row::new()
+            .spacing(10)
+            .align_items(Alignment::Center)
+            .push(text(format!("{}. Load order:", self.profile)));
+        // Reloading would lose unsaved edits.
+        let can_reload = self.unsaved.is_empty();
+        for (index, layer) in self.layers.iter().enumerate() {
+            load_order = load_order.push(text(&layer.name));
+            if index > 0 {
+                load_order = load_order.push(
+                    button(text("×").size(12))
+                        .style(theme::Button::Text)
+                        .on_press_maybe(can_reload.then_some(Message::RemoveMod(index))),
+                );
+            }
+        }
+        load_order = load_order
+            .push(button("Add mod...").on_press_maybe(can_reload.then_some(Message::AddMod)))
+            .push(
+                button("Add .mod file...")
+                    .on_press_maybe(can_reload.then_some(Message::AddModDescriptor)),
+            );
+        if !can_reload {
+            load_order = load_order.push(text(UNSAVED_EDITS));
+        }
+
+        let mut header = row![
+            pick_list(
+                &CategoryFilter::ALL[..],
+                Some(self.category),
+                Message::CategorySelected
+            ),
             combo_box,
-            vertical_space().height(50),
-            scrollable(selected_file),
+            encoding,
+            errors_button
         ]
-        .width(Length::Fill)
-        .align_items(Alignment::Center)
-        .spacing(10);
+        .spacing(20)
+        .align_items(Alignment::Center);
+        if !self.localisation.is_empty() {
+            header = header.push(pick_list(
+                self.localisation
+                    .languages()
+                    .map(str::to_owned)
+                    .collect::<Vec<_>>(),
+                Some(self.language.clone()),
+                Message::LanguageSelected,
+            ));
+        }
+
+        let mut content = column![header, load_order]
+            .width(Length::Fill)
+            .align_items(Alignment::Center)
+            .spacing(10);
+
+        if self.show_errors {
+            let mut errors = Column::new().spacing(5);
+
+            for file in problems {
+                let summary = format!(
+                    "{}: {}, {} pairs, {:.1} ms",
+                    file.path,
+                    file.status,
+                    file.pairs,
+                    file.parse_time.as_secs_f64() * 1000.0
+                );
+                let style = match file.status {
+                    FileStatus::Errors => theme::Button::Destructive,
+                    _ => theme::Button::Secondary,
+                };
+
+                errors = errors.push(
+                    button(text(summary))
+                        .style(style)
+                        .on_press(Message::Selected(file.path.clone())),
+                );
+                if let Some(error) = &file.error {
+                    errors = errors.push(text(format!("    {}", error)).size(14));
+                }
+                for diagnostic in &file.diagnostics {
+                    errors = errors.push(text(format!("    {}", diagnostic)).size(14));
+                }
+            }
+
+            content = content.push(
+                container(scrollable(errors).width(Length::Fill))
+                    .padding(10)
+                    .height(250),
+            );
+        }
+
+        content = content.push(
+            row![
+                text_input("Search keys and values...", &self.search_query)
+                    .on_input(Message::SearchChanged)
+                    .on_submit(Message::Search)
+                    .width(400),
+                pick_list(
+                    &SearchTarget::ALL[..],
+                    Some(self.search_options.target),
+                    Message::SearchTargetSelected
+                ),
+                pick_list(
+                    &MatchMode::ALL[..],
+                    Some(self.search_options.mode),
+                    Message::MatchModeSelected
+                ),
+                checkbox("Match case", self.search_options.case_sensitive)
+                    .on_toggle(Message::MatchCase),
+                button("Search").on_press(Message::Search),
+            ]
+            .spacing(10)
+            .align_items(Alignment::Center),
+        );
+
+        content = content.push(
+            row![
+                text_input("Query, such as technologies/*/allow/tag", &self.query)
+                    .on_input(Message::QueryChanged)
+                    .on_submit(Message::RunQuery)
+                    .width(400),
+                checkbox("Open file only", self.query_open_file_only)
+                    .on_toggle(Message::QueryOpenFileOnly),
+                button("Run query").on_press(Message::RunQuery),
+            ]
+            .spacing(10)
+            .align_items(Alignment::Center),
+        );
+
+        if let Some(error) = &self.search_error {
+            content = content.push(text(error));
+        } else if !self.search_results.is_empty() {
+            let mut results = Column::new().spacing(5);
+
+            for (index, result) in self
+                .search_results
+                .iter()
+                .enumerate()
+                .take(MAX_SEARCH_RESULTS)
+            {
+                results = results.push(
+                    button(text(format!(
+                        "{}:{} {} {}",
+                        result.file,
+                        result.span.line,
+                        result.path_string(),
+                        result.value
+                    )))
+                    .style(theme::Button::Text)
+                    .on_press(Message::OpenSearchResult(index)),
+                );
+            }
+
+            content = content.push(text(format!(
+                "{} results{}",
+                self.search_results.len(),
+                if self.search_results.len() > MAX_SEARCH_RESULTS {
+                    format!(", showing the first {}", MAX_SEARCH_RESULTS)
+                } else {
+                    String::new()
+                }
+            )));
+            content = content.push(
+                container(scrollable(results).width(Length::Fill))
+                    .padding(10)
+                    .height(250),
+            );
+        }
+
+        let content = content
+            .push(vertical_space().height(50))
+            .push(selected_file);
 
         container(content)
             .width(Length::Fill)
@@ -275,28 +1479,445 @@ impl DataView {
     }
 }
 
-fn map_values(pair: &ConfigPair) -> DataValue {
+/// Maps pairs to rows in source order. When `group_duplicates` is set, pairs sharing a key
+/// are gathered under one row at the position of the first of them. `parent` is the path
+/// of the pair the pairs are in.
+fn map_pairs(pairs: &[ConfigPair], group_duplicates: bool, parent: &[usize]) -> Vec<DataValue> {
+    let values = pairs.iter().enumerate().map(|(index, pair)| {
+        let mut path = parent.to_vec();
+        path.push(index);
+        map_values(pair, group_duplicates, path)
+    });
+
+    if !group_duplicates {
+        return values.collect();
+    }
+
+    let mut groups: Vec<Vec<DataValue>> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+
+    for value in values {
+        match positions.get(&value.identifier) {
+            Some(&position) => groups[position].push(value),
+            None => {
+                positions.insert(value.identifier.clone(), groups.len());
+                groups.push(vec![value]);
+            }
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|mut group| {
+            if group.len() == 1 {
+                return group.pop().unwrap();
+            }
+
+            DataValue {
+                id: Uuid::new_v4().to_string(),
+                identifier: group[0].identifier.clone(),
+                sign: String::new(),
+                value: format!("({} entries)", group.len()),
+                span: group[0].span,
+                path: None,
+                is_block: false,
+                overridden_by: None,
+                localised: None,
+                resolved: None,
+                open: false,
+                visible: true,
+                children: group,
+            }
+        })
+        .collect()
+}
+
+fn map_values(pair: &ConfigPair, group_duplicates: bool, path: Vec<usize>) -> DataValue {
     match pair.value {
         ConfigValue::Object(ref children) => DataValue {
             id: Uuid::new_v4().to_string(),
             identifier: pair.identifier.clone(),
             sign: pair.sign.clone(),
             value: "...".to_string(),
+            span: pair.span,
+            children: map_pairs(children, group_duplicates, &path),
+            path: Some(path),
+            is_block: true,
+            overridden_by: None,
+            localised: None,
+            resolved: None,
             open: false,
-            children: children.iter().map(|pair| map_values(&pair)).collect(),
+            visible: true,
         },
         _ => DataValue {
             id: Uuid::new_v4().to_string(),
             identifier: pair.identifier.clone(),
             sign: pair.sign.clone(),
             value: pair.value.to_string(),
+            span: pair.span,
+            path: Some(path),
+            is_block: false,
+            overridden_by: None,
+            localised: None,
+            resolved: None,
             open: false,
+            visible: true,
             children: vec![],
         },
     }
 }
 
-async fn parse(path: PathBuf) -> Arc<HashMap<String, Vec<ConfigPair>>> {
-    let data = parse_game(&path);
-    Arc::new(data)
+/// Looks up the localised text of every row in `language`, by key or else by value. Blocks
+/// are only looked up by key.
+fn localise(values: &mut [DataValue], localisation: &Localisation, language: &str) {
+    for value in values {
+        value.localised = localisation
+            .get(language, &value.identifier)
+            .or_else(|| {
+                if value.is_block {
+                    None
+                } else {
+                    localisation.get(language, value.value.trim_matches('"'))
+                }
+            })
+            .map(|text| render(text, localisation, language));
+        localise(&mut value.children, localisation, language);
+    }
+}
+
+/// Resolves the values of the rows of `pairs` that are scripted variables or inline math,
+/// with `scripted` and the variables `pairs` declares before them.
+fn resolve_variables(values: &mut [DataValue], pairs: &[ConfigPair], scripted: &Variables) {
+    let mut variables = scripted.nested();
+    let mut resolved = HashMap::new();
+    for (index, pair) in pairs.iter().enumerate() {
+        resolve_pair(pair, vec![index], &variables, &mut resolved);
+        variables.declare(std::slice::from_ref(pair));
+    }
+
+    set_resolved(values, &resolved);
+}
+
+/// Resolves `pair` at `path`, and the pairs in it, into `resolved` by path.
+fn resolve_pair(
+    pair: &ConfigPair,
+    path: Vec<usize>,
+    variables: &Variables,
+    resolved: &mut HashMap<Vec<usize>, Result<String, VariableError>>,
+) {
+    match &pair.value {
+        ConfigValue::Variable(_) | ConfigValue::Math(_) => {
+            let value = variables.resolve(&pair.value);
+            resolved.insert(path, value.map(|value| value.to_string()));
+        }
+        ConfigValue::Object(children) => {
+            for (index, child) in children.iter().enumerate() {
+                let mut path = path.clone();
+                path.push(index);
+                resolve_pair(child, path, variables, resolved);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn set_resolved(
+    values: &mut [DataValue],
+    resolved: &HashMap<Vec<usize>, Result<String, VariableError>>,
+) {
+    for value in values {
+        if let Some(result) = value.path.as_ref().and_then(|path| resolved.get(path)) {
+            value.resolved = Some(result.clone());
+        }
+        set_resolved(&mut value.children, resolved);
+    }
+}
+
+/// Shows rendered localisation markup in quotes, a text per segment, as iced has no rich
+/// text. Icons are shown by name, and line breaks as spaces to keep rows one line high.
+fn markup_row(markup: &Markup) -> Row<'static, Message> {
+    let default = Color::from_rgb(0.5, 0.7, 0.9);
+    let mut row = Row::new().push(text("\"").style(default));
+
+    for segment in &markup.segments {
+        let colour = segment
+            .colour
+            .and_then(markup::colour)
+            .map(|(r, g, b)| Color::from_rgb8(r, g, b))
+            .unwrap_or(default);
+        row = row.push(match segment.kind {
+            SegmentKind::Text => text(segment.text.replace('\n', " ")).style(colour),
+            SegmentKind::Icon => {
+                text(format!("£{}£", segment.text)).style(Color::from_rgb(0.9, 0.6, 0.2))
+            }
+            SegmentKind::Unresolved => text(&segment.text).style(Color::from_rgb(0.9, 0.4, 0.4)),
+        });
+    }
+
+    row.push(text("\"").style(default))
+}
+
+/// Finds the row of the pair at `path`.
+fn find_value<'a>(values: &'a [DataValue], path: &[usize]) -> Option<&'a DataValue> {
+    values.iter().find_map(|value| {
+        if value.path.as_deref() == Some(path) {
+            Some(value)
+        } else {
+            find_value(&value.children, path)
+        }
+    })
+}
+
+/// Paths of the open rows, so they can be opened again after the rows are rebuilt.
+fn open_paths(values: &[DataValue], paths: &mut HashSet<Vec<usize>>) {
+    for value in values {
+        if value.open {
+            if let Some(path) = &value.path {
+                paths.insert(path.clone());
+            }
+        }
+        open_paths(&value.children, paths);
+    }
+}
+
+fn reopen(values: &mut [DataValue], paths: &HashSet<Vec<usize>>) {
+    for value in values {
+        reopen(&mut value.children, paths);
+
+        if value.path.as_ref().is_some_and(|path| paths.contains(path))
+            || value.path.is_none() && value.children.iter().any(|child| child.open)
+        {
+            value.open = true;
+        }
+    }
+}
+
+/// The pairs in the block of the pair at `path`, or `pairs` itself for an empty path.
+fn pairs_at<'a>(pairs: &'a [ConfigPair], path: &[usize]) -> Option<&'a [ConfigPair]> {
+    let Some((index, rest)) = path.split_first() else {
+        return Some(pairs);
+    };
+
+    match &pairs.get(*index)?.value {
+        ConfigValue::Object(children) => pairs_at(children, rest),
+        _ => None,
+    }
+}
+
+/// The rows of the tree that are shown, in order, with how deep each one is and where it is
+/// in `values`, as found by [`row_at`].
+fn visible_rows(values: &[DataValue]) -> Vec<(usize, Vec<usize>)> {
+    fn push(values: &[DataValue], position: &mut Vec<usize>, rows: &mut Vec<(usize, Vec<usize>)>) {
+        for (index, value) in values.iter().enumerate() {
+            if !value.visible {
+                continue;
+            }
+
+            position.push(index);
+            rows.push((position.len() - 1, position.clone()));
+            if value.open {
+                push(&value.children, position, rows);
+            }
+            position.pop();
+        }
+    }
+
+    let mut rows = Vec::new();
+    push(values, &mut Vec::new(), &mut rows);
+    rows
+}
+
+/// The row at `position`: the index of a top level row, then of a child, and so on.
+fn row_at<'a>(values: &'a [DataValue], position: &[usize]) -> &'a DataValue {
+    let (first, rest) = position.split_first().expect("positions are never empty");
+    rest.iter()
+        .fold(&values[*first], |value, index| &value.children[*index])
+}
+
+/// Hides the rows that do not contain `filter` in their key or value, except for the
+/// ancestors of matches, which are opened, and the descendants of matches. Returns the
+/// number of matches. An empty filter shows every row.
+fn filter_values(values: &mut [DataValue], filter: &str) -> usize {
+    fn filter_value(value: &mut DataValue, filter: &str, in_match: bool) -> usize {
+        let is_match = !filter.is_empty()
+            && (value.identifier.to_lowercase().contains(filter)
+                || value.value.to_lowercase().contains(filter));
+
+        let mut child_matches = 0;
+        let mut child_visible = false;
+        for child in value.children.iter_mut() {
+            child_matches += filter_value(child, filter, in_match || is_match);
+            child_visible |= child.visible;
+        }
+
+        value.visible = filter.is_empty() || is_match || in_match || child_visible;
+        if child_matches > 0 {
+            value.open = true;
+        }
+
+        child_matches + is_match as usize
+    }
+
+    let filter = filter.to_lowercase();
+    values
+        .iter_mut()
+        .map(|value| filter_value(value, &filter, false))
+        .sum()
+}
+
+/// Opens every row leading to the pair starting at `start`. Returns whether `value` is or
+/// contains that pair.
+fn expand_to(value: &mut DataValue, start: usize) -> bool {
+    if value.span.start == start && value.children.is_empty() {
+        return true;
+    }
+
+    let mut found = false;
+    for child in value.children.iter_mut() {
+        found |= expand_to(child, start);
+    }
+
+    if found {
+        value.open = true;
+    }
+
+    found || value.span.start == start
+}
+
+impl Drop for DataView {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Loads the game on its own threads, so the executor is not blocked, reporting progress
+/// about every half percent and finishing with [`Message::Loaded`] or
+/// [`Message::LoadingCancelled`].
+fn load(
+    layers: Vec<Layer>,
+    profile: Profile,
+    cancel: Arc<AtomicBool>,
+) -> impl Stream<Item = Message> {
+    let (sender, receiver) = mpsc::unbounded();
+
+    std::thread::spawn(move || {
+        let data = parse_layers_with_progress(&layers, &profile, &cancel, |progress| {
+            let step = (progress.total / 200).max(1);
+
+            if progress.done % step == 0 || progress.done == progress.total {
+                let _ = sender.unbounded_send(Message::Progress(progress));
+            }
+        });
+
+        let _ = sender.unbounded_send(match data {
+            Some(data) => Message::Loaded(Arc::new(data)),
+            None => Message::LoadingCancelled,
+        });
+    });
+
+    receiver
+}
+
+async fn save_file(
+    path: PathBuf,
+    file: String,
+    text: String,
+    encoding: Encoding,
+) -> Result<String, Error> {
+    let bytes = encode(&text, encoding).ok_or_else(|| {
+        Error::Io(format!(
+            "the file contains characters that cannot be written as {}",
+            encoding
+        ))
+    })?;
+
+    tokio::fs::write(&path, bytes)
+        .await
+        .map_err(|e| Error::Io(e.to_string()))?;
+
+    Ok(file)
+}
+
+/// Picks a mod folder, or a `.mod` descriptor when `descriptor` is set.
+async fn pick_mod(descriptor: bool) -> Result<PathBuf, Error> {
+    let dialog = rfd::AsyncFileDialog::new();
+    let picked = if descriptor {
+        dialog
+            .set_title("Choose mod descriptor...")
+            .add_filter("Mod descriptor", &["mod"])
+            .pick_file()
+            .await
+    } else {
+        dialog.set_title("Choose mod folder...").pick_folder().await
+    };
+
+    Ok(picked.ok_or(Error::DialogClosed)?.path().to_owned())
+}
+
+async fn export_file(file: String, pairs: Vec<ConfigPair>) -> Result<PathBuf, Error> {
+    let name = Path::new(&file).file_stem().unwrap_or_default();
+
+    let picked_file = rfd::AsyncFileDialog::new()
+        .set_title("Export file...")
+        .set_file_name(format!("{}.json", name.to_string_lossy()))
+        .add_filter("JSON", &["json"])
+        .add_filter("YAML", &["yaml", "yml"])
+        .add_filter("TOML", &["toml"])
+        .save_file()
+        .await
+        .ok_or(Error::DialogClosed)?;
+
+    let path = picked_file.path().to_owned();
+    let format = path
+        .extension()
+        .and_then(|extension| ExportFormat::from_extension(&extension.to_string_lossy()))
+        .unwrap_or(ExportFormat::Json);
+
+    let exported = export(&pairs, format).map_err(|e| Error::Io(e.to_string()))?;
+    tokio::fs::write(&path, exported)
+        .await
+        .map_err(|e| Error::Io(e.to_string()))?;
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    use clausewitz_viewer::game::parse_layers;
+    use clausewitz_viewer::parser::parse_config_file;
+
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_leave_added_pair() {
+        let dir = TempDir::new("data-view-add-pair");
+        let root = dir.path();
+        fs::create_dir_all(root.join("common")).unwrap();
+        fs::write(root.join("common/a.txt"), "a = 1\n").unwrap();
+        fs::write(root.join("common/b.txt"), "b = 2\n").unwrap();
+
+        let layers = vec![Layer::game(root)];
+        let game = parse_layers(&layers, &Profile::default());
+        let (mut view, _) = DataView::new(layers, Profile::default());
+        view.cancel.store(true, Ordering::Relaxed);
+        let _ = view.update(Message::Loaded(Arc::new(game)));
+
+        let _ = view.update(Message::Selected("common/a.txt".to_owned()));
+        let _ = view.update(Message::AddPair(Vec::new()));
+        assert_eq!(view.data["common/a.txt"].pairs.len(), 2);
+        assert!(view.unsaved.contains("common/a.txt"));
+
+        let _ = view.update(Message::Selected("common/b.txt".to_owned()));
+        assert_eq!(
+            view.data["common/a.txt"].pairs,
+            parse_config_file("a = 1\n").unwrap()
+        );
+        assert!(view.editing.is_none());
+        assert!(view.unsaved.is_empty());
+        assert!(!view.history["common/a.txt"].can_undo());
+        assert!(!view.history["common/a.txt"].can_redo());
+    }
 }
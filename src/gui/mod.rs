@@ -6,6 +6,9 @@ use iced::{Application, Command, Element, Font, Length, Settings, Subscription};
 
 use std::path::PathBuf;
 
+use clausewitz_viewer::mods::Layer;
+use clausewitz_viewer::profile::Profile;
+
 mod data_view;
 
 pub fn run() -> iced::Result {
@@ -19,7 +22,7 @@ pub fn run() -> iced::Result {
 enum View {
     #[default]
     Default,
-    DataView(data_view::DataView),
+    DataView(Box<data_view::DataView>),
 }
 
 #[derive(Default)]
@@ -32,7 +35,7 @@ struct ClausewitzViewer {
 enum Message {
     OpenPath,
     PathOpened(Result<PathBuf, Error>),
-    DataViewMessage(data_view::Message),
+    DataView(data_view::Message),
 }
 
 impl Application for ClausewitzViewer {
@@ -59,18 +62,26 @@ impl Application for ClausewitzViewer {
         match message {
             Message::OpenPath => Command::perform(open_path(), Message::PathOpened),
             Message::PathOpened(result) => {
-                if let Ok(path) = result {
-                    self.file = Some(path.clone());
+                let Ok(path) = result else {
+                    return Command::none();
+                };
+                if let View::DataView(view) = &mut self.view {
+                    if !view.can_reload() {
+                        return Command::none();
+                    }
                 }
 
-                let (view, task) = data_view::DataView::new(self.file.as_ref().unwrap().clone());
-                self.view = View::DataView(view);
+                let profile = Profile::detect(&path);
+                let layers = vec![Layer::game(&profile.script_root(&path))];
+                self.file = Some(path);
+                let (view, task) = data_view::DataView::new(layers, profile);
+                self.view = View::DataView(Box::new(view));
 
-                task.map(Message::DataViewMessage)
+                task.map(Message::DataView)
             }
-            Message::DataViewMessage(message) => {
+            Message::DataView(message) => {
                 if let View::DataView(view) = &mut self.view {
-                    let _ = view.update(message);
+                    return view.update(message).map(Message::DataView);
                 }
 
                 Command::none()
@@ -81,23 +92,28 @@ impl Application for ClausewitzViewer {
     fn subscription(&self) -> Subscription<Message> {
         keyboard::on_key_press(|key, modifiers| match key.as_ref() {
             keyboard::Key::Character("o") if modifiers.command() => Some(Message::OpenPath),
+            keyboard::Key::Character("z" | "Z") if modifiers.command() => {
+                Some(Message::DataView(if modifiers.shift() {
+                    data_view::Message::Redo
+                } else {
+                    data_view::Message::Undo
+                }))
+            }
             _ => None,
         })
     }
 
-    fn view(&self) -> Element<Message> {
+    fn view(&self) -> Element<'_, Message> {
         let controls = row![button("Open Clausewitz Game Path...").on_press(Message::OpenPath)];
 
         match &self.view {
-            View::Default => {
-                return container(controls)
-                    .center_x()
-                    .center_y()
-                    .width(Length::Fill)
-                    .height(Length::Fill)
-                    .into()
-            }
-            View::DataView(view) => return view.view().map(|m| Message::DataViewMessage(m)),
+            View::Default => container(controls)
+                .center_x()
+                .center_y()
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .into(),
+            View::DataView(view) => view.view().map(Message::DataView),
         }
     }
 
@@ -109,6 +125,7 @@ impl Application for ClausewitzViewer {
 #[derive(Debug, Clone)]
 pub enum Error {
     DialogClosed,
+    Io(String),
 }
 
 async fn open_path() -> Result<PathBuf, Error> {
@@ -0,0 +1,153 @@
+//! Undo and redo for [`Edit`]s, kept separately for every file.
+
+use crate::edit::Edit;
+
+/// The undo and redo stacks of one file.
+///
+/// Each entry is a group of edits that were made together and is undone in one step, such
+/// as changing the key and the value of a pair at once.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct History {
+    undo: Vec<Vec<Edit>>,
+    redo: Vec<Vec<Edit>>,
+}
+
+impl History {
+    /// Records `undo`, the edits that undo a change just made. Changes that were undone
+    /// can no longer be redone after this.
+    pub fn record(&mut self, undo: Vec<Edit>) {
+        if undo.is_empty() {
+            return;
+        }
+
+        self.undo.push(undo);
+        self.redo.clear();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Takes the last change off the history without undoing it, returning the edits that
+    /// undo it, for a change that is abandoned rather than undone. It cannot be redone.
+    pub fn discard_last(&mut self) -> Option<Vec<Edit>> {
+        self.undo.pop()
+    }
+
+    /// Undoes the last change with `apply`, which applies edits in order and returns the
+    /// edits that undo them. Returns `None` when there is nothing to undo. The history is
+    /// left as it was when `apply` fails.
+    pub fn undo<E>(
+        &mut self,
+        apply: impl FnOnce(&[Edit]) -> Result<Vec<Edit>, E>,
+    ) -> Option<Result<(), E>> {
+        step(&mut self.undo, &mut self.redo, apply)
+    }
+
+    /// Redoes the last undone change, like [`undo`](Self::undo).
+    pub fn redo<E>(
+        &mut self,
+        apply: impl FnOnce(&[Edit]) -> Result<Vec<Edit>, E>,
+    ) -> Option<Result<(), E>> {
+        step(&mut self.redo, &mut self.undo, apply)
+    }
+}
+
+fn step<E>(
+    from: &mut Vec<Vec<Edit>>,
+    to: &mut Vec<Vec<Edit>>,
+    apply: impl FnOnce(&[Edit]) -> Result<Vec<Edit>, E>,
+) -> Option<Result<(), E>> {
+    let edits = from.pop()?;
+
+    Some(match apply(&edits) {
+        Ok(inverse) => {
+            to.push(inverse);
+            Ok(())
+        }
+        Err(e) => {
+            from.push(edits);
+            Err(e)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cst::{parse_syntax_tree, SyntaxTree};
+    use crate::edit::{apply, parse_pair, parse_value, EditError};
+
+    const INPUT: &str = "tag = SWE\ncapital = 1\n";
+
+    fn apply_all(tree: &mut SyntaxTree, edits: &[Edit]) -> Result<Vec<Edit>, EditError> {
+        let mut undo = edits
+            .iter()
+            .map(|edit| apply(tree, edit))
+            .collect::<Result<Vec<Edit>, EditError>>()?;
+        undo.reverse();
+        Ok(undo)
+    }
+
+    #[test]
+    fn test_history() {
+        let mut tree = parse_syntax_tree(INPUT).unwrap();
+        let mut history = History::default();
+
+        for edits in [
+            vec![Edit::SetValue {
+                path: vec![1],
+                value: parse_value("2").unwrap(),
+            }],
+            vec![
+                Edit::Insert {
+                    path: vec![0],
+                    pair: parse_pair("owner = FIN").unwrap(),
+                    comment: None,
+                },
+                Edit::Move {
+                    from: vec![2],
+                    to: vec![0],
+                },
+            ],
+        ] {
+            history.record(apply_all(&mut tree, &edits).unwrap());
+        }
+        assert_eq!(tree.to_string(), "capital = 2\nowner = FIN\ntag = SWE\n");
+
+        assert!(history.undo(|edits| apply_all(&mut tree, edits)).is_some());
+        assert_eq!(tree.to_string(), "tag = SWE\ncapital = 2\n");
+        assert!(history.undo(|edits| apply_all(&mut tree, edits)).is_some());
+        assert_eq!(tree.to_string(), INPUT);
+        assert!(history.undo(|edits| apply_all(&mut tree, edits)).is_none());
+
+        assert!(history.redo(|edits| apply_all(&mut tree, edits)).is_some());
+        assert_eq!(tree.to_string(), "tag = SWE\ncapital = 2\n");
+        assert!(history.can_redo());
+
+        history.record(apply_all(&mut tree, &[Edit::Remove { path: vec![0] }]).unwrap());
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn test_history_failed_undo() {
+        let mut history = History::default();
+        history.record(vec![Edit::Remove { path: vec![0] }]);
+
+        let result = history.undo(|_| Err("no"));
+        assert_eq!(result, Some(Err("no")));
+        assert!(history.can_undo());
+        assert!(!history.can_redo());
+
+        assert_eq!(
+            history.discard_last(),
+            Some(vec![Edit::Remove { path: vec![0] }])
+        );
+        assert!(!history.can_undo());
+        assert!(!history.can_redo());
+    }
+}
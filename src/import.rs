@@ -0,0 +1,335 @@
+//! Import of JSON documents as Clausewitz script, the reverse of [`export`](crate::export).
+//!
+//! The document has to follow the mapping described in the export module. In addition,
+//! `true` and `false` become `yes` and `no`. Strings are written without quotes when they
+//! can be, and strings spelled like dates become dates.
+
+use std::fmt::{self, Display, Formatter};
+
+use serde_json::{Map, Value};
+
+use crate::export::{MATH, NAMED, OP, REPEAT};
+use crate::format::{format_pairs, FormatOptions};
+use crate::parser::{ConfigElement, ConfigPair, ConfigValue};
+
+const SIGNS: [&str; 8] = ["<=", ">=", "!=", "?=", "=", "<", ">", "+"];
+
+#[derive(Debug)]
+pub enum ImportError {
+    Json(serde_json::Error),
+    /// A value that has no Clausewitz equivalent, with the keys leading to it.
+    Unsupported {
+        path: String,
+        message: String,
+    },
+}
+
+impl Display for ImportError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ImportError::Json(e) => write!(f, "{}", e),
+            ImportError::Unsupported { path, message } if path.is_empty() => {
+                write!(f, "{}", message)
+            }
+            ImportError::Unsupported { path, message } => write!(f, "{}: {}", path, message),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// Parses a JSON document and formats it as script.
+pub fn import(json: &str, options: &FormatOptions) -> Result<String, ImportError> {
+    let value = serde_json::from_str(json).map_err(ImportError::Json)?;
+    let pairs = from_json(&value)?;
+
+    Ok(format_pairs(&pairs, options))
+}
+
+/// Converts a JSON object to pairs.
+pub fn from_json(value: &Value) -> Result<Vec<ConfigPair>, ImportError> {
+    match value {
+        Value::Object(object) => object_to_pairs(object, ""),
+        _ => Err(unsupported("", "the document must be an object")),
+    }
+}
+
+fn object_to_pairs(
+    object: &Map<String, Value>,
+    path: &str,
+) -> Result<Vec<ConfigPair>, ImportError> {
+    let mut pairs = Vec::new();
+
+    for (key, value) in object {
+        let path = if path.is_empty() {
+            key.clone()
+        } else {
+            format!("{}/{}", path, key)
+        };
+        let identifier = if is_bare(key) {
+            key.clone()
+        } else {
+            format!("\"{}\"", escape(key))
+        };
+
+        let repeated = match value {
+            Value::Object(object) if object.len() == 1 && object.contains_key(REPEAT) => {
+                match &object[REPEAT] {
+                    Value::Array(values) => values.iter().collect(),
+                    _ => return Err(unsupported(&path, "`$repeat` must be an array")),
+                }
+            }
+            value => vec![value],
+        };
+
+        for value in repeated {
+            let (sign, value) = sign_and_value(value, &path)?;
+            pairs.push(ConfigPair::new(
+                &identifier,
+                sign,
+                value_from_json(value, &path)?,
+            ));
+        }
+    }
+
+    Ok(pairs)
+}
+
+/// Splits an `$op` object into its sign and value.
+fn sign_and_value<'a>(value: &'a Value, path: &str) -> Result<(&'a str, &'a Value), ImportError> {
+    let Value::Object(object) = value else {
+        return Ok(("=", value));
+    };
+    let Some(op) = object.get(OP) else {
+        return Ok(("=", value));
+    };
+
+    let sign = op
+        .as_str()
+        .filter(|sign| SIGNS.contains(sign))
+        .ok_or_else(|| unsupported(path, &format!("`{}` is not a comparison sign", op)))?;
+    let value = object
+        .get("value")
+        .ok_or_else(|| unsupported(path, "`$op` without a `value`"))?;
+
+    Ok((sign, value))
+}
+
+fn value_from_json(value: &Value, path: &str) -> Result<ConfigValue, ImportError> {
+    match value {
+        Value::Null => Err(unsupported(path, "null has no equivalent")),
+        Value::Bool(true) => Ok(ConfigValue::Identifier("yes".to_owned())),
+        Value::Bool(false) => Ok(ConfigValue::Identifier("no".to_owned())),
+        Value::Number(number) => Ok(ConfigValue::Number(number.as_f64().unwrap())),
+        Value::String(string) => Ok(string_to_value(string)),
+        Value::Array(values) => Ok(ConfigValue::Array(
+            values
+                .iter()
+                .map(|value| value_from_json(value, path).map(ConfigElement::from))
+                .collect::<Result<_, _>>()?,
+        )),
+        Value::Object(object) => match (object.get(MATH), object.get(NAMED)) {
+            (Some(expression), _) => expression
+                .as_str()
+                .filter(|expression| !expression.contains([']', '\n']))
+                .map(|expression| ConfigValue::Math(expression.to_owned()))
+                .ok_or_else(|| {
+                    unsupported(path, &format!("`{}` is not an expression", expression))
+                }),
+            (None, Some(name)) => {
+                let name = name
+                    .as_str()
+                    .filter(|name| {
+                        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphabetic())
+                    })
+                    .ok_or_else(|| unsupported(path, &format!("`{}` is not a valid name", name)))?;
+                let values = match object.get("values") {
+                    Some(Value::Array(values)) => values
+                        .iter()
+                        .map(|value| value_from_json(value, path).map(ConfigElement::from))
+                        .collect::<Result<_, _>>()?,
+                    _ => return Err(unsupported(path, "`$named` without `values`")),
+                };
+                Ok(ConfigValue::Named(name.to_owned(), values))
+            }
+            (None, None) => Ok(ConfigValue::Object(object_to_pairs(object, path)?)),
+        },
+    }
+}
+
+fn string_to_value(string: &str) -> ConfigValue {
+    if let Some((year, month, day)) = parse_date(string) {
+        return ConfigValue::Date(year, month, day);
+    }
+
+    // Scripted variables are exported as they are written. Inline math is not read from
+    // strings, as quoted text may look the same, but from `$math`.
+    if let Some(name) = string.strip_prefix('@').filter(|name| {
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }) {
+        return ConfigValue::Variable(name.to_owned());
+    }
+
+    if is_bare(string) {
+        ConfigValue::Identifier(string.to_owned())
+    } else {
+        ConfigValue::String(escape(string))
+    }
+}
+
+fn parse_date(string: &str) -> Option<(u16, u8, u8)> {
+    let parts: Vec<&str> = string.split('.').collect();
+    let [year, month, day] = parts[..] else {
+        return None;
+    };
+
+    let digits = |part: &str, lengths: &[usize]| {
+        lengths.contains(&part.len()) && part.chars().all(|c| c.is_ascii_digit())
+    };
+    if !digits(year, &[4]) || !digits(month, &[1, 2]) || !digits(day, &[1, 2]) {
+        return None;
+    }
+
+    Some((year.parse().ok()?, month.parse().ok()?, day.parse().ok()?))
+}
+
+/// Whether `string` can be written without quotes. Text starting with `@[` would read
+/// back as inline math.
+fn is_bare(string: &str) -> bool {
+    !string.is_empty()
+        && !string.starts_with("@[")
+        && !string
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '"' | '{' | '}' | '=' | '#'))
+}
+
+/// Escapes quotes that are not escaped already, so `string` can be put between quotes.
+/// Escape sequences are left alone, as the parser keeps them in the strings it reads.
+fn escape(string: &str) -> String {
+    let mut escaped_string = String::new();
+    let mut escaped = false;
+
+    for c in string.chars() {
+        if c == '"' && !escaped {
+            escaped_string.push('\\');
+        }
+        escaped = c == '\\' && !escaped;
+        escaped_string.push(c);
+    }
+    if escaped {
+        escaped_string.push('\\');
+    }
+
+    escaped_string
+}
+
+fn unsupported(path: &str, message: &str) -> ImportError {
+    ImportError::Unsupported {
+        path: path.to_owned(),
+        message: message.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::to_json;
+    use crate::parser::parse_config_file;
+    use serde_json::json;
+
+    #[test]
+    fn test_import_round_trip() {
+        let input = r#"
+            namespace = flavor_swe
+            country_event = {
+                id = flavor_swe.1
+                trigger = { tag = SWE num_of_cities > 5 a ?= b }
+                option = { name = flavor_swe.1.a add_prestige = 10 }
+                option = { name = flavor_swe.1.b add_prestige = -2.5 }
+            }
+            country_event = { id = flavor_swe.2 }
+            color = rgb { 10 20 30 }
+            provinces = { 1 2 3 }
+            1444.11.11 = { owner = SWE }
+            start = 1444.11.11
+            @base = 10
+            scaled = @[ base * 2 ]
+            unscaled = @base
+        "#;
+
+        let pairs = parse_config_file(input).unwrap();
+        let imported = import(&to_json(&pairs).to_string(), &FormatOptions::default()).unwrap();
+
+        assert_eq!(parse_config_file(&imported).unwrap(), pairs);
+    }
+
+    #[test]
+    fn test_from_json() {
+        let value = json!({
+            "name": "New Sweden",
+            "quote": "say \"hi\"",
+            "capital": 1,
+            "is_active": true,
+            "add_core": { "$repeat": ["SWE", { "$op": "!=", "value": "FIN" }] },
+            "unscaled": "@base",
+            "scaled": { "$math": " base * 2 " },
+            "text": "@[ base ]",
+            "bare_text": "@[x]",
+        });
+
+        assert_eq!(
+            from_json(&value).unwrap(),
+            vec![
+                ConfigPair::new("name", "=", ConfigValue::String("New Sweden".to_string())),
+                ConfigPair::new(
+                    "quote",
+                    "=",
+                    ConfigValue::String("say \\\"hi\\\"".to_string())
+                ),
+                ConfigPair::new("capital", "=", ConfigValue::Number(1.0)),
+                ConfigPair::new("is_active", "=", ConfigValue::Identifier("yes".to_string())),
+                ConfigPair::new("add_core", "=", ConfigValue::Identifier("SWE".to_string())),
+                ConfigPair::new("add_core", "!=", ConfigValue::Identifier("FIN".to_string())),
+                ConfigPair::new("unscaled", "=", ConfigValue::Variable("base".to_string())),
+                ConfigPair::new("scaled", "=", ConfigValue::Math(" base * 2 ".to_string())),
+                ConfigPair::new("text", "=", ConfigValue::String("@[ base ]".to_string())),
+                ConfigPair::new("bare_text", "=", ConfigValue::String("@[x]".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_import_output_parses() {
+        let json = r#"{ "a key": { "b": "\"quoted\" text", "c": [] } }"#;
+
+        let imported = import(json, &FormatOptions::default()).unwrap();
+
+        assert_eq!(
+            imported,
+            "\"a key\" = {\n\tb = \"\\\"quoted\\\" text\"\n\tc = { }\n}\n"
+        );
+        assert!(parse_config_file(&imported).is_ok());
+
+        let imported = import(r#"{ "a": "@[x]" }"#, &FormatOptions::default()).unwrap();
+        assert_eq!(imported, "a = \"@[x]\"\n");
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let error = import(r#"{ "a": { "b": null } }"#, &FormatOptions::default()).unwrap_err();
+        assert_eq!(error.to_string(), "a/b: null has no equivalent");
+
+        let error = import(
+            r#"{ "a": { "$op": "~", "value": 1 } }"#,
+            &FormatOptions::default(),
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "a: `\"~\"` is not a comparison sign");
+
+        let error = import(r#"{ "a": { "$math": 2 } }"#, &FormatOptions::default()).unwrap_err();
+        assert_eq!(error.to_string(), "a: `2` is not an expression");
+
+        assert!(import("[1, 2]", &FormatOptions::default()).is_err());
+        assert!(import("{", &FormatOptions::default()).is_err());
+    }
+}
@@ -0,0 +1,19 @@
+pub mod cst;
+pub mod edit;
+pub mod export;
+pub mod file;
+pub mod format;
+pub mod game;
+pub mod history;
+pub mod import;
+pub mod localisation;
+pub mod markup;
+pub mod mods;
+pub mod parser;
+pub mod profile;
+pub mod query;
+pub mod report;
+pub mod search;
+#[cfg(test)]
+mod test_util;
+pub mod variables;
@@ -0,0 +1,331 @@
+//! Localisation files, which map keys to the text shown in game, one file per language.
+//!
+//! They look like YAML but are not quite:
+//!
+//! ```text
+//! l_english:
+//!  NAME_SWE:0 "Sweden" # a comment
+//!  flavor_swe.1.t: "The "Great" Power"
+//! ```
+//!
+//! The header names the language, every entry has a key, an optional version number after
+//! the colon, and text in quotes that may contain unescaped quotes itself.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+use rayon::prelude::*;
+
+use crate::file::read_file;
+use crate::game::relative_path;
+use crate::mods::{resolve_files, Layer, LayerFile};
+use crate::parser::{Diagnostic, Severity, Span};
+use crate::profile::{Extensions, FileCategory, Profile};
+use crate::report::FileReport;
+
+/// Folders localisation is kept in. The newer games spell it with a `z`.
+const FOLDERS: [&str; 2] = ["localisation", "localization"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalisationEntry {
+    pub key: String,
+    /// The number after the colon, which the game ignores.
+    pub version: Option<u32>,
+    pub text: String,
+    pub span: Span,
+}
+
+/// A parsed localisation file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LocalisationFile {
+    /// Language from the header, such as `english` for `l_english:`.
+    pub language: String,
+    pub entries: Vec<LocalisationEntry>,
+    /// Lines that could not be parsed, which are skipped.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Parses a localisation file, skipping lines that cannot be parsed.
+pub fn parse_localisation(text: &str) -> LocalisationFile {
+    let mut file = LocalisationFile::default();
+    let mut offset = 0;
+
+    for (index, line) in text.split_inclusive('\n').enumerate() {
+        let start = offset;
+        offset += line.len();
+
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let indent = line.len() - line.trim_start().len();
+        let span = Span {
+            start: start + indent,
+            end: start + line.trim_end().len(),
+            line: index + 1,
+            column: line[..indent].chars().count() + 1,
+        };
+
+        if file.language.is_empty() {
+            match trimmed
+                .strip_prefix("l_")
+                .and_then(|header| header.split('#').next())
+                .and_then(|header| header.trim_end().strip_suffix(':'))
+            {
+                Some(language) => file.language = language.to_owned(),
+                None => file.diagnostics.push(Diagnostic {
+                    severity: Severity::Error,
+                    message: "expected a language header such as `l_english:`".to_owned(),
+                    span,
+                }),
+            }
+            continue;
+        }
+
+        match parse_entry(trimmed) {
+            Some((key, version, text)) => file.entries.push(LocalisationEntry {
+                key: key.to_owned(),
+                version,
+                text: unescape(text),
+                span,
+            }),
+            None => file.diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                message: "expected an entry such as `key:0 \"text\"`".to_owned(),
+                span,
+            }),
+        }
+    }
+
+    file
+}
+
+/// Splits `key:0 "text"` into its parts. The text ends at the last quote on the line, so
+/// it can contain quotes and be followed by a comment.
+fn parse_entry(line: &str) -> Option<(&str, Option<u32>, &str)> {
+    let (key, rest) = line.split_once(':')?;
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return None;
+    }
+
+    let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+    let version = rest[..digits].parse().ok();
+    let rest = rest[digits..].trim_start();
+
+    let text = rest.strip_prefix('"')?;
+    let end = text.rfind('"')?;
+    Some((key, version, &text[..end]))
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("\\\"", "\"").replace("\\n", "\n")
+}
+
+/// The text of every key, per language.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Localisation {
+    languages: BTreeMap<String, HashMap<String, String>>,
+}
+
+impl Localisation {
+    /// Adds the entries of `file`. Keys that already have a text keep it, like in game,
+    /// unless `replace` is set, as it is for files in a `replace` folder.
+    pub fn insert(&mut self, file: &LocalisationFile, replace: bool) {
+        let texts = self.languages.entry(file.language.clone()).or_default();
+
+        for entry in &file.entries {
+            if replace || !texts.contains_key(&entry.key) {
+                texts.insert(entry.key.clone(), entry.text.clone());
+            }
+        }
+    }
+
+    pub fn get(&self, language: &str, key: &str) -> Option<&str> {
+        self.languages.get(language)?.get(key).map(String::as_str)
+    }
+
+    /// Every language with at least one file, sorted.
+    pub fn languages(&self) -> impl Iterator<Item = &str> {
+        self.languages.keys().map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.languages.is_empty()
+    }
+}
+
+/// Finds the localisation files of `layers`, the game first. A file in a later layer
+/// replaces the file with the same path in earlier ones. See [`resolve_files`].
+pub fn localisation_files(layers: &[Layer]) -> (Vec<LayerFile>, Vec<(usize, PathBuf, io::Error)>) {
+    let mut extensions = Extensions::new();
+    extensions.insert("yml", FileCategory::Localisation);
+    let profile = Profile {
+        folders: FOLDERS.iter().map(|folder| folder.to_string()).collect(),
+        extensions,
+        ..Profile::default()
+    };
+
+    resolve_files(layers, &profile)
+}
+
+/// Loads the localisation of `layers`, found by [`localisation_files`]. Returns a report
+/// for every file.
+pub fn load_localisation(layers: &[Layer]) -> (Localisation, Vec<FileReport>) {
+    let (files, unreadable) = localisation_files(layers);
+
+    load_localisation_files(layers, &files, &unreadable, &AtomicBool::new(false), |_| {})
+        .expect("loading is never cancelled")
+}
+
+/// Like [`load_localisation`], but loads `files` and reports `unreadable` folders, as found
+/// by [`localisation_files`]. Calls `on_loaded` with the report of each file after it is
+/// parsed, and returns `None` when `cancel` gets set before every file is parsed.
+pub fn load_localisation_files(
+    layers: &[Layer],
+    files: &[LayerFile],
+    unreadable: &[(usize, PathBuf, io::Error)],
+    cancel: &AtomicBool,
+    on_loaded: impl Fn(&FileReport) + Sync,
+) -> Option<(Localisation, Vec<FileReport>)> {
+    let mut parsed: Vec<(String, Option<LocalisationFile>, FileReport)> = files
+        .par_iter()
+        .map(|file| {
+            if cancel.load(Ordering::Relaxed) {
+                return None;
+            }
+            let start = Instant::now();
+
+            let (parsed, report) = match read_file(&file.path) {
+                Ok(source) => {
+                    let parsed = parse_localisation(&source.text);
+                    let report = FileReport::new(
+                        file.name.clone(),
+                        start.elapsed(),
+                        parsed.entries.len(),
+                        parsed.diagnostics.clone(),
+                    );
+                    (Some(parsed), report)
+                }
+                Err(e) => (None, FileReport::unreadable(file.name.clone(), &e)),
+            };
+            on_loaded(&report);
+
+            Some((file.name.clone(), parsed, report))
+        })
+        .collect::<Option<_>>()?;
+
+    // Files in a `replace` folder go last, so their texts win.
+    let is_replace = |name: &str| name.split('/').any(|folder| folder == "replace");
+    parsed.sort_by_key(|(name, _, _)| (is_replace(name), name.clone()));
+
+    let mut localisation = Localisation::default();
+    let mut reports: Vec<FileReport> = unreadable
+        .iter()
+        .map(|(layer, folder, e)| {
+            FileReport::unreadable(relative_path(&layers[*layer].root, folder), e)
+        })
+        .collect();
+
+    for (name, file, report) in parsed {
+        if let Some(file) = file.filter(|file| !file.language.is_empty()) {
+            localisation.insert(&file, is_replace(&name));
+        }
+        reports.push(report);
+    }
+
+    Some((localisation, reports))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::fs;
+
+    const INPUT: &str = "\u{feff}l_english: # English\n # Countries\n NAME_SWE:0 \"Sweden\"\n flavor_swe.1.t: \"The \"Great\" Power\" # title\n flavor_swe.1.d:1 \"Line\\nbreak \\\"quoted\\\"\"\n broken \"text\"\n";
+
+    #[test]
+    fn test_parse_localisation() {
+        let file = parse_localisation(INPUT.trim_start_matches('\u{feff}'));
+
+        assert_eq!(file.language, "english");
+        let entries: Vec<(&str, Option<u32>, &str)> = file
+            .entries
+            .iter()
+            .map(|entry| (entry.key.as_str(), entry.version, entry.text.as_str()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("NAME_SWE", Some(0), "Sweden"),
+                ("flavor_swe.1.t", None, "The \"Great\" Power"),
+                ("flavor_swe.1.d", Some(1), "Line\nbreak \"quoted\""),
+            ]
+        );
+        assert_eq!(file.entries[0].span.line, 3);
+        assert_eq!(file.entries[0].span.column, 2);
+        assert_eq!(file.diagnostics.len(), 1);
+        assert_eq!(file.diagnostics[0].span.line, 6);
+
+        let missing_header = parse_localisation(" NAME_SWE:0 \"Sweden\"\n");
+        assert!(missing_header.entries.is_empty());
+        assert_eq!(missing_header.diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn test_load_localisation() {
+        let dir = TempDir::new("localisation");
+        let root = dir.path();
+        let game = root.join("game");
+        let mod_root = root.join("mod");
+        fs::create_dir_all(game.join("localisation/replace")).unwrap();
+        fs::create_dir_all(mod_root.join("localisation")).unwrap();
+        fs::write(
+            game.join("localisation/a_l_english.yml"),
+            "l_english:\n A:0 \"a\"\n B:0 \"b\"\n",
+        )
+        .unwrap();
+        fs::write(
+            game.join("localisation/b_l_english.yml"),
+            "l_english:\n A:0 \"not a\"\n C:0 \"c\"\n",
+        )
+        .unwrap();
+        fs::write(
+            game.join("localisation/replace/z_l_english.yml"),
+            "l_english:\n B:0 \"replaced b\"\n",
+        )
+        .unwrap();
+        fs::write(
+            game.join("localisation/a_l_german.yml"),
+            "l_german:\n A:0 \"ä\"\n",
+        )
+        .unwrap();
+        fs::write(
+            mod_root.join("localisation/b_l_english.yml"),
+            "l_english:\n C:0 \"modded c\"\n",
+        )
+        .unwrap();
+
+        let layers = [Layer::game(&game), Layer::game(&mod_root)];
+        let (localisation, reports) = load_localisation(&layers);
+        let (files, unreadable) = localisation_files(&layers);
+        let cancelled =
+            load_localisation_files(&layers, &files, &unreadable, &AtomicBool::new(true), |_| {});
+
+        assert_eq!(
+            localisation.languages().collect::<Vec<_>>(),
+            ["english", "german"]
+        );
+        assert_eq!(localisation.get("english", "A"), Some("a"));
+        assert_eq!(localisation.get("english", "B"), Some("replaced b"));
+        assert_eq!(localisation.get("english", "C"), Some("modded c"));
+        assert_eq!(localisation.get("german", "A"), Some("ä"));
+        assert_eq!(localisation.get("german", "B"), None);
+        assert_eq!(reports.len(), 4);
+        assert!(cancelled.is_none());
+    }
+}
@@ -1,8 +1,23 @@
-mod file;
-mod game;
+use std::process::ExitCode;
+
+use clap::Parser;
+
+mod cli;
 mod gui;
-mod parser;
+#[cfg(test)]
+mod test_util;
+
+pub fn main() -> ExitCode {
+    let args = cli::Args::parse();
 
-pub fn main() -> iced::Result {
-    gui::run()
+    match args.command {
+        Some(command) => cli::run(command, &args.load),
+        None => match gui::run() {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("{}", e);
+                ExitCode::FAILURE
+            }
+        },
+    }
 }
@@ -0,0 +1,268 @@
+//! The markup of localisation texts, rendered into styled segments.
+//!
+//! ```text
+//! §YYellow§! text with an £adm£ icon, [Root.GetName] and $OTHER_KEY$ in it
+//! ```
+//!
+//! Colour codes start with `§` and a letter and end with `§!`, and can be nested. `£icon£`
+//! shows an icon, `[...]` is filled in by the game from the scope it is shown in, and
+//! `$KEY$` is replaced by the text of another key, or by a value the game fills in.
+
+use std::fmt::{self, Display, Formatter};
+
+use crate::localisation::Localisation;
+
+/// How deep `$KEY$` substitutions are followed, so keys referring to each other do not
+/// loop forever.
+const MAX_DEPTH: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentKind {
+    Text,
+    /// An icon, whose name is the text.
+    Icon,
+    /// A reference that could not be resolved, shown as written.
+    Unresolved,
+}
+
+/// A run of text in one style.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub text: String,
+    pub kind: SegmentKind,
+    /// The colour code in effect, such as `Y` for `§Y`.
+    pub colour: Option<char>,
+}
+
+/// A reference in a text that could not be resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reference {
+    /// `$KEY$` without a text for `KEY`, usually a value the game fills in.
+    Variable(String),
+    /// `[Root.GetName]`, filled in by the game from the scope the text is shown in.
+    Scope(String),
+}
+
+impl Display for Reference {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Reference::Variable(name) => write!(f, "${}$", name),
+            Reference::Scope(name) => write!(f, "[{}]", name),
+        }
+    }
+}
+
+/// A rendered text.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Markup {
+    pub segments: Vec<Segment>,
+    /// Every reference that could not be resolved, in order.
+    pub unresolved: Vec<Reference>,
+}
+
+impl Markup {
+    /// The text without any styling.
+    pub fn plain_text(&self) -> String {
+        self.segments
+            .iter()
+            .map(|segment| segment.text.as_str())
+            .collect()
+    }
+}
+
+/// Renders `text`, replacing `$KEY$` with the text of `KEY` in `language` where there is
+/// one.
+pub fn render(text: &str, localisation: &Localisation, language: &str) -> Markup {
+    let mut renderer = Renderer {
+        localisation,
+        language,
+        markup: Markup::default(),
+        colours: Vec::new(),
+    };
+    renderer.render(text, 0);
+    renderer.markup
+}
+
+/// The RGB colour of a colour code, as the games show it. `None` for unknown codes.
+pub fn colour(code: char) -> Option<(u8, u8, u8)> {
+    Some(match code {
+        'W' => (255, 255, 255),
+        'B' => (80, 150, 255),
+        'G' => (50, 200, 50),
+        'R' => (230, 50, 50),
+        'b' => (0, 0, 0),
+        'g' => (160, 160, 160),
+        'Y' => (255, 220, 50),
+        'O' => (255, 150, 30),
+        'T' => (255, 255, 255),
+        'M' => (200, 80, 200),
+        'l' => (200, 200, 200),
+        'J' => (230, 130, 130),
+        _ => return None,
+    })
+}
+
+struct Renderer<'a> {
+    localisation: &'a Localisation,
+    language: &'a str,
+    markup: Markup,
+    /// Colour codes in effect, innermost last.
+    colours: Vec<char>,
+}
+
+impl Renderer<'_> {
+    fn render(&mut self, text: &str, depth: usize) {
+        let mut rest = text;
+
+        while let Some(start) = rest.find(['§', '£', '[', '$']) {
+            self.push(&rest[..start], SegmentKind::Text);
+            rest = &rest[start..];
+
+            let mut chars = rest.chars();
+            let marker = chars.next().unwrap();
+            let after = chars.as_str();
+
+            if marker == '§' {
+                match chars.next() {
+                    Some('!') => {
+                        self.colours.pop();
+                    }
+                    Some(code) => self.colours.push(code),
+                    None => {}
+                }
+                rest = chars.as_str();
+                continue;
+            }
+
+            let end = if marker == '[' { ']' } else { marker };
+            let Some(length) = after.find(end) else {
+                // Not closed, so not markup.
+                self.push(&rest[..marker.len_utf8()], SegmentKind::Text);
+                rest = after;
+                continue;
+            };
+            let inner = &after[..length];
+            rest = &after[length + end.len_utf8()..];
+
+            match marker {
+                '£' => self.push(inner, SegmentKind::Icon),
+                '[' => self.unresolved(Reference::Scope(inner.to_owned())),
+                _ => {
+                    // `$KEY|Y$` shows `KEY` formatted, which does not matter here.
+                    let key = inner.split('|').next().unwrap_or_default();
+                    match self.localisation.get(self.language, key) {
+                        Some(text) if depth < MAX_DEPTH => self.render(text, depth + 1),
+                        _ => self.unresolved(Reference::Variable(key.to_owned())),
+                    }
+                }
+            }
+        }
+
+        self.push(rest, SegmentKind::Text);
+    }
+
+    fn unresolved(&mut self, reference: Reference) {
+        self.push(&reference.to_string(), SegmentKind::Unresolved);
+        self.markup.unresolved.push(reference);
+    }
+
+    /// Adds `text`, joining it to the last segment when that has the same style.
+    fn push(&mut self, text: &str, kind: SegmentKind) {
+        if text.is_empty() && kind == SegmentKind::Text {
+            return;
+        }
+
+        let colour = self.colours.last().copied();
+        match self.markup.segments.last_mut() {
+            Some(last)
+                if kind == SegmentKind::Text && last.kind == kind && last.colour == colour =>
+            {
+                last.text.push_str(text)
+            }
+            _ => self.markup.segments.push(Segment {
+                text: text.to_owned(),
+                kind,
+                colour,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::localisation::parse_localisation;
+
+    fn localisation() -> Localisation {
+        let mut localisation = Localisation::default();
+        localisation.insert(
+            &parse_localisation(
+                "l_english:\n ADM:0 \"§GAdmin§!\"\n LOOP:0 \"$LOOP$\"\n EMPTY:0 \"\"\n",
+            ),
+            false,
+        );
+        localisation
+    }
+
+    fn segments(markup: &Markup) -> Vec<(&str, SegmentKind, Option<char>)> {
+        markup
+            .segments
+            .iter()
+            .map(|segment| (segment.text.as_str(), segment.kind, segment.colour))
+            .collect()
+    }
+
+    #[test]
+    fn test_render() {
+        let markup = render(
+            "§YGain §R50§! £adm£ $ADM$ power§! for [Root.GetName]$EMPTY$ $COUNTRY|Y$",
+            &localisation(),
+            "english",
+        );
+
+        assert_eq!(
+            segments(&markup),
+            [
+                ("Gain ", SegmentKind::Text, Some('Y')),
+                ("50", SegmentKind::Text, Some('R')),
+                (" ", SegmentKind::Text, Some('Y')),
+                ("adm", SegmentKind::Icon, Some('Y')),
+                (" ", SegmentKind::Text, Some('Y')),
+                ("Admin", SegmentKind::Text, Some('G')),
+                (" power", SegmentKind::Text, Some('Y')),
+                (" for ", SegmentKind::Text, None),
+                ("[Root.GetName]", SegmentKind::Unresolved, None),
+                (" ", SegmentKind::Text, None),
+                ("$COUNTRY$", SegmentKind::Unresolved, None),
+            ]
+        );
+        assert_eq!(
+            markup.unresolved,
+            [
+                Reference::Scope("Root.GetName".to_owned()),
+                Reference::Variable("COUNTRY".to_owned()),
+            ]
+        );
+        assert_eq!(
+            markup.plain_text(),
+            "Gain 50 adm Admin power for [Root.GetName] $COUNTRY$"
+        );
+    }
+
+    #[test]
+    fn test_render_malformed() {
+        let localisation = localisation();
+
+        let markup = render("100$ and [open", &localisation, "english");
+        assert_eq!(markup.plain_text(), "100$ and [open");
+        assert!(markup.unresolved.is_empty());
+
+        let markup = render("$LOOP$", &localisation, "english");
+        assert_eq!(markup.unresolved, [Reference::Variable("LOOP".to_owned())]);
+
+        let markup = render("$ADM$", &localisation, "german");
+        assert_eq!(markup.plain_text(), "$ADM$");
+        assert_eq!(colour('Y'), Some((255, 220, 50)));
+        assert_eq!(colour('?'), None);
+    }
+}
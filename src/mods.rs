@@ -0,0 +1,467 @@
+//! Loading mods on top of the game, the way the engine does.
+//!
+//! The game and each mod are a [`Layer`]. A file in a later layer replaces the file with
+//! the same path in earlier layers, and a `replace_path` in a mod's descriptor hides every
+//! file of earlier layers in that folder. Within a folder, files are loaded by name no
+//! matter what layer they come from, and [`MergeRules`] decide what happens when several
+//! of them define the same key.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::file::read_file;
+use crate::game::{find_files, relative_path, ParsedFile};
+use crate::parser::{parse_config_file_recovering, ConfigValue};
+use crate::profile::Profile;
+
+/// A folder files are loaded from: the game itself, or a mod on top of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layer {
+    pub name: String,
+    pub root: PathBuf,
+    /// Folders whose files from earlier layers are left out, from `replace_path`.
+    pub replace_paths: Vec<String>,
+}
+
+impl Layer {
+    /// The game folder at `root`, or a mod without a descriptor.
+    pub fn game(root: &Path) -> Self {
+        Layer {
+            name: root.file_name().map_or_else(
+                || root.display().to_string(),
+                |name| name.to_string_lossy().into_owned(),
+            ),
+            root: root.to_owned(),
+            replace_paths: Vec::new(),
+        }
+    }
+
+    /// A mod, from its folder or its `.mod` descriptor.
+    ///
+    /// A folder may have a `descriptor.mod` in it. The `path` of a descriptor outside the mod
+    /// folder is relative to the folder above the one the descriptor is in, like the
+    /// launcher's `mod/name.mod` with `path = "mod/name"`.
+    pub fn from_mod(path: &Path) -> io::Result<Self> {
+        if path.is_dir() {
+            let descriptor = path.join(DESCRIPTOR);
+            let descriptor = if descriptor.is_file() {
+                ModDescriptor::parse(&read_file(&descriptor)?.text)
+            } else {
+                ModDescriptor::default()
+            };
+
+            return Ok(descriptor.into_layer(path.to_owned()));
+        }
+
+        let descriptor = ModDescriptor::parse(&read_file(&path.to_owned())?.text);
+        let folder = descriptor.path.as_ref().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} has no `path`", path.display()),
+            )
+        })?;
+
+        let folder = Path::new(folder);
+        let root = if folder.is_absolute() {
+            folder.to_owned()
+        } else {
+            path.parent()
+                .and_then(Path::parent)
+                .unwrap_or(Path::new(""))
+                .join(folder)
+        };
+
+        if !root.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not a folder", root.display()),
+            ));
+        }
+        Ok(descriptor.into_layer(root))
+    }
+}
+
+/// The parts of a `.mod` file that matter for loading.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ModDescriptor {
+    pub name: Option<String>,
+    pub path: Option<String>,
+    pub replace_paths: Vec<String>,
+}
+
+impl ModDescriptor {
+    /// Parses a descriptor, skipping anything it does not understand.
+    pub fn parse(text: &str) -> Self {
+        let mut descriptor = ModDescriptor::default();
+
+        for pair in parse_config_file_recovering(text).pairs {
+            let value = match pair.value {
+                ConfigValue::String(value) | ConfigValue::Identifier(value) => value,
+                _ => continue,
+            };
+
+            match pair.identifier.as_str() {
+                "name" => descriptor.name = Some(value),
+                "path" => descriptor.path = Some(value),
+                "replace_path" => descriptor
+                    .replace_paths
+                    .push(value.replace('\\', "/").trim_matches('/').to_owned()),
+                _ => {}
+            }
+        }
+
+        descriptor
+    }
+
+    fn into_layer(self, root: PathBuf) -> Layer {
+        let mut layer = Layer::game(&root);
+        if let Some(name) = self.name {
+            layer.name = name;
+        }
+        layer.replace_paths = self.replace_paths;
+        layer
+    }
+}
+
+/// The descriptor a mod folder may have, see [`Layer::from_mod`].
+const DESCRIPTOR: &str = "descriptor.mod";
+
+/// A file to load, and the index of the layer it is loaded from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayerFile {
+    /// Path relative to the layer.
+    pub name: String,
+    pub path: PathBuf,
+    pub layer: usize,
+}
+
+/// Finds the files to load from `layers`, the game first: for every relative path, the
+/// file of the last layer that has it, sorted by path. Only the folders and extensions of
+/// `profile` are looked at, so files right in a layer are only found when it has no
+/// folders. The descriptors of mods are left out: they describe the layer rather than
+/// being loaded, and every mod has one at the same path. Folders that cannot be read are
+/// returned separately with their layer and error.
+pub fn resolve_files(
+    layers: &[Layer],
+    profile: &Profile,
+) -> (Vec<LayerFile>, Vec<(usize, PathBuf, io::Error)>) {
+    let mut files = BTreeMap::new();
+    let mut unreadable = Vec::new();
+
+    for (index, layer) in layers.iter().enumerate() {
+        for folder in &layer.replace_paths {
+            files.retain(|name: &String, _| folder_of(name) != folder);
+        }
+
+        let mut found = Vec::new();
+        if profile.folders.is_empty() {
+            let (files, errors) = find_files(&layer.root, &profile.extensions);
+            found.extend(files);
+            unreadable.extend(errors.into_iter().map(|(path, e)| (index, path, e)));
+        }
+        // Mods only have some of the folders.
+        for folder in &profile.folders {
+            let folder = layer.root.join(folder);
+            if !folder.is_dir() {
+                continue;
+            }
+
+            let (files, errors) = find_files(&folder, &profile.extensions);
+            found.extend(files);
+            unreadable.extend(errors.into_iter().map(|(path, e)| (index, path, e)));
+        }
+
+        for path in found {
+            let name = relative_path(&layer.root, &path);
+            if index > 0 && name == DESCRIPTOR {
+                continue;
+            }
+            files.insert(
+                name.clone(),
+                LayerFile {
+                    name,
+                    path,
+                    layer: index,
+                },
+            );
+        }
+    }
+
+    (files.into_values().collect(), unreadable)
+}
+
+/// The folder a relative path is in, such as `common/ideas` for
+/// `common/ideas/00_ideas.txt`, or an empty string for files at the top.
+pub fn folder_of(name: &str) -> &str {
+    name.rsplit_once('/').map_or("", |(folder, _)| folder)
+}
+
+/// What happens when several files in a folder define the same top level key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeRule {
+    /// Only whole files replace each other, and keys in different files are unrelated.
+    Files,
+    /// The definition loaded last is used.
+    LastWins,
+    /// The definition loaded first is used, and later ones are ignored.
+    FirstWins,
+    /// Every definition is used, as their contents are merged.
+    Merge,
+}
+
+/// The [`MergeRule`] of every folder. A folder without its own rule uses the rule of the
+/// closest folder above it that has one, and [`MergeRule::Files`] when none does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeRules {
+    rules: HashMap<String, MergeRule>,
+}
+
+impl MergeRules {
+    /// No rules, so every folder uses [`MergeRule::Files`].
+    pub fn new() -> Self {
+        MergeRules {
+            rules: HashMap::new(),
+        }
+    }
+
+    pub fn set(&mut self, folder: &str, rule: MergeRule) {
+        self.rules.insert(folder.trim_matches('/').to_owned(), rule);
+    }
+
+    pub fn rule(&self, folder: &str) -> MergeRule {
+        let mut folder = folder;
+
+        loop {
+            if let Some(rule) = self.rules.get(folder) {
+                return *rule;
+            }
+            match folder.rsplit_once('/') {
+                Some((parent, _)) => folder = parent,
+                None => return MergeRule::Files,
+            }
+        }
+    }
+}
+
+impl Default for MergeRules {
+    /// Objects in `common` replace each other, except for country files, which are only
+    /// replaced whole, and on actions, which are merged.
+    fn default() -> Self {
+        let mut rules = MergeRules::new();
+        rules.set("common", MergeRule::LastWins);
+        rules.set("common/countries", MergeRule::Files);
+        rules.set("common/on_actions", MergeRule::Merge);
+        rules
+    }
+}
+
+/// A top level pair defining a key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    pub file: String,
+    /// Index of the pair among the top level pairs of the file.
+    pub index: usize,
+    pub layer: usize,
+}
+
+/// Every top level definition in folders whose rule is not [`MergeRule::Files`], so it can
+/// be told which of them is used.
+#[derive(Debug, Clone, Default)]
+pub struct Definitions {
+    /// Keyed by folder and key, in load order.
+    definitions: HashMap<(String, String), Vec<Definition>>,
+    rules: MergeRules,
+}
+
+impl Definitions {
+    pub fn new(files: &HashMap<String, ParsedFile>, rules: &MergeRules) -> Self {
+        let mut names: Vec<&String> = files
+            .keys()
+            .filter(|name| rules.rule(folder_of(name)) != MergeRule::Files)
+            .collect();
+        // Files in a folder are loaded by name, so sorting the paths sorts each folder.
+        names.sort();
+
+        let mut definitions: HashMap<(String, String), Vec<Definition>> = HashMap::new();
+        for name in names {
+            let file = &files[name];
+
+            for (index, pair) in file.pairs.iter().enumerate() {
+                definitions
+                    .entry((folder_of(name).to_owned(), pair.identifier.clone()))
+                    .or_default()
+                    .push(Definition {
+                        file: name.clone(),
+                        index,
+                        layer: file.layer,
+                    });
+            }
+        }
+
+        Definitions {
+            definitions,
+            rules: rules.clone(),
+        }
+    }
+
+    /// The definitions of `key` in `folder`, in load order.
+    pub fn get(&self, folder: &str, key: &str) -> &[Definition] {
+        self.definitions
+            .get(&(folder.to_owned(), key.to_owned()))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// The definition used instead of `key`, the pair at `index` in `file`, if another
+    /// one is.
+    pub fn overridden_by(&self, file: &str, index: usize, key: &str) -> Option<&Definition> {
+        let folder = folder_of(file);
+        let definitions = self.get(folder, key);
+
+        let used = match self.rules.rule(folder) {
+            MergeRule::LastWins => definitions.last()?,
+            MergeRule::FirstWins => definitions.first()?,
+            MergeRule::Files | MergeRule::Merge => return None,
+        };
+
+        (used.file != file || used.index != index).then_some(used)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    use crate::game::parse_layers;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_mod_descriptor() {
+        let descriptor = ModDescriptor::parse(
+            "name = \"Better Ideas\"\npath = \"mod/better_ideas\"\nreplace_path = \"common/ideas/\"\ntags = { \"Gameplay\" }\n",
+        );
+
+        assert_eq!(
+            descriptor,
+            ModDescriptor {
+                name: Some("Better Ideas".to_owned()),
+                path: Some("mod/better_ideas".to_owned()),
+                replace_paths: vec!["common/ideas".to_owned()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_load_order() {
+        let dir = TempDir::new("load-order");
+        let root = dir.path();
+        let game = root.join("game");
+        let user = root.join("user");
+        fs::create_dir_all(game.join("common/ideas")).unwrap();
+        fs::create_dir_all(game.join("common/on_actions")).unwrap();
+        fs::create_dir_all(game.join("events")).unwrap();
+        fs::create_dir_all(user.join("mod/first/common/ideas")).unwrap();
+        fs::create_dir_all(user.join("mod/first/common/on_actions")).unwrap();
+        fs::create_dir_all(user.join("mod/second/events")).unwrap();
+        fs::create_dir_all(user.join("mod/second/common/ideas")).unwrap();
+
+        fs::write(game.join("common/ideas/00_ideas.txt"), "a = 1\nb = 1\n").unwrap();
+        fs::write(game.join("common/ideas/10_ideas.txt"), "c = 1\n").unwrap();
+        fs::write(
+            game.join("common/on_actions/00_on.txt"),
+            "on_startup = { }\n",
+        )
+        .unwrap();
+        fs::write(game.join("events/a.txt"), "x = 1\n").unwrap();
+        fs::write(game.join("events/b.txt"), "x = 1\n").unwrap();
+        fs::write(user.join("mod/first/common/ideas/00_ideas.txt"), "a = 2\n").unwrap();
+        fs::write(
+            user.join("mod/first/common/ideas/05_ideas.txt"),
+            "a = 3\nc = 3\n",
+        )
+        .unwrap();
+        fs::write(
+            user.join("mod/first/common/on_actions/01_on.txt"),
+            "on_startup = { }\n",
+        )
+        .unwrap();
+        fs::write(user.join("mod/first/descriptor.mod"), "name = \"First\"\n").unwrap();
+        fs::write(user.join("mod/second/events/c.txt"), "y = 1\n").unwrap();
+        fs::write(user.join("mod/second/common/ideas/20_ideas.txt"), "b = 2\n").unwrap();
+        fs::write(
+            user.join("mod/second.mod"),
+            "name = \"Second\"\npath = \"mod/second\"\nreplace_path = \"events\"\n",
+        )
+        .unwrap();
+
+        let layers = vec![
+            Layer::game(&game),
+            Layer::from_mod(&user.join("mod/first")).unwrap(),
+            Layer::from_mod(&user.join("mod/second.mod")).unwrap(),
+        ];
+        let missing = Layer::from_mod(&user.join("mod/missing.mod"));
+        let (files, _) = resolve_files(&layers, &Profile::default());
+        let loaded = parse_layers(&layers, &Profile::default());
+
+        assert!(missing.is_err());
+        assert_eq!(layers[1].name, "First");
+        assert_eq!(layers[2].name, "Second");
+        assert_eq!(layers[2].root, user.join("mod/second"));
+
+        let files: Vec<(&str, usize)> = files
+            .iter()
+            .map(|file| (file.name.as_str(), file.layer))
+            .collect();
+        assert_eq!(
+            files,
+            [
+                ("common/ideas/00_ideas.txt", 1),
+                ("common/ideas/05_ideas.txt", 1),
+                ("common/ideas/10_ideas.txt", 0),
+                ("common/ideas/20_ideas.txt", 2),
+                ("common/on_actions/00_on.txt", 0),
+                ("common/on_actions/01_on.txt", 1),
+                ("events/c.txt", 2),
+            ]
+        );
+        assert_eq!(loaded.layers, layers);
+        assert_eq!(loaded.files["common/ideas/00_ideas.txt"].layer, 1);
+
+        let definitions = Definitions::new(&loaded.files, &MergeRules::default());
+        let overridden_by = |file: &str, index: usize, key: &str| {
+            definitions
+                .overridden_by(file, index, key)
+                .map(|definition| (definition.file.clone(), definition.layer))
+        };
+
+        assert_eq!(definitions.get("common/ideas", "a").len(), 2);
+        assert_eq!(
+            overridden_by("common/ideas/00_ideas.txt", 0, "a"),
+            Some(("common/ideas/05_ideas.txt".to_owned(), 1))
+        );
+        assert_eq!(overridden_by("common/ideas/05_ideas.txt", 0, "a"), None);
+        assert_eq!(
+            overridden_by("common/ideas/05_ideas.txt", 1, "c"),
+            Some(("common/ideas/10_ideas.txt".to_owned(), 0))
+        );
+        assert_eq!(
+            overridden_by("common/on_actions/00_on.txt", 0, "on_startup"),
+            None
+        );
+        assert_eq!(overridden_by("events/c.txt", 0, "y"), None);
+    }
+
+    #[test]
+    fn test_merge_rules() {
+        let mut rules = MergeRules::default();
+        rules.set("common/ideas/", MergeRule::FirstWins);
+
+        assert_eq!(rules.rule("common/ideas"), MergeRule::FirstWins);
+        assert_eq!(rules.rule("common/buildings"), MergeRule::LastWins);
+        assert_eq!(rules.rule("common/on_actions"), MergeRule::Merge);
+        assert_eq!(rules.rule("common/countries"), MergeRule::Files);
+        assert_eq!(rules.rule("events"), MergeRule::Files);
+        assert_eq!(rules.rule(""), MergeRule::Files);
+    }
+}
@@ -1,9 +1,12 @@
 use std::fmt::{self, Display, Formatter};
 
-use pest::error::Error;
+use pest::error::{Error, InputLocation};
+use pest::iterators::Pair;
 use pest::Parser;
 use pest_derive::Parser;
 
+use crate::format::{format_pairs, format_value, FormatOptions};
+
 #[derive(Parser)]
 #[grammar = "config.pest"]
 struct ConfigParser;
@@ -11,136 +14,422 @@ struct ConfigParser;
 #[derive(Debug, PartialEq, Clone)]
 pub enum ConfigValue {
     Object(Vec<ConfigPair>),
-    Array(Vec<ConfigValue>),
+    Array(Vec<ConfigElement>),
     String(String),
     Number(f64),
     Identifier(String),
     Date(u16, u8, u8),
-    Named(String, Vec<ConfigValue>),
+    Named(String, Vec<ConfigElement>),
+    /// A scripted variable, such as `@my_var`, by its name without the `@`.
+    Variable(String),
+    /// Inline math, such as `@[ my_var * 2 ]`, by the expression between the brackets.
+    Math(String),
 }
 
 impl Display for ConfigValue {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        match self {
-            ConfigValue::Object(object) => {
-                write!(f, "{{\n")?;
-                for pair in object {
-                    write!(f, "   {}", pair)?;
-                }
-                write!(f, "}}")
-            }
-            ConfigValue::Array(array) => {
-                write!(f, "{{ ")?;
-                for value in array {
-                    write!(f, "{} ", value)?;
-                }
-                write!(f, "}}")
-            }
-            ConfigValue::String(string) => write!(f, "\"{}\"", string),
-            ConfigValue::Number(number) => write!(f, "{}", number),
-            ConfigValue::Identifier(identifier) => write!(f, "{}", identifier),
-            ConfigValue::Date(year, month, day) => write!(f, "{}.{}.{}", year, month, day),
-            ConfigValue::Named(name, values) => {
-                write!(f, "{} ", name)?;
-                for value in values {
-                    write!(f, "{} ", value)?;
-                }
-                write!(f, "")
-            }
-        }
+        write!(f, "{}", format_value(self, &FormatOptions::default()))
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Clone)]
 pub struct ConfigPair {
     pub identifier: String,
     pub sign: String,
     pub value: ConfigValue,
+    /// Location of the whole pair, from the start of the key to the end of the value.
+    pub span: Span,
+    /// Location of the value only.
+    pub value_span: Span,
+}
+
+impl ConfigPair {
+    pub fn new(identifier: &str, sign: &str, value: ConfigValue) -> Self {
+        ConfigPair {
+            identifier: identifier.to_owned(),
+            sign: sign.to_owned(),
+            value,
+            span: Span::default(),
+            value_span: Span::default(),
+        }
+    }
+}
+
+/// Pairs compare by content only; where they were parsed from does not matter.
+impl PartialEq for ConfigPair {
+    fn eq(&self, other: &Self) -> bool {
+        self.identifier == other.identifier && self.sign == other.sign && self.value == other.value
+    }
 }
 
 impl Display for ConfigPair {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "{} {} {}\n", self.identifier, self.sign, self.value)
+        let pairs = std::slice::from_ref(self);
+        write!(f, "{}", format_pairs(pairs, &FormatOptions::default()))
+    }
+}
+
+/// A value in an array or a named block, such as `255` in `rgb { 255 0 0 }`.
+#[derive(Debug, Clone)]
+pub struct ConfigElement {
+    pub value: ConfigValue,
+    /// Location of the value.
+    pub span: Span,
+}
+
+impl From<ConfigValue> for ConfigElement {
+    fn from(value: ConfigValue) -> Self {
+        ConfigElement {
+            value,
+            span: Span::default(),
+        }
+    }
+}
+
+/// Elements compare by content only, like [`ConfigPair`]s.
+impl PartialEq for ConfigElement {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+/// Location of a parsed element in the source text.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct Span {
+    /// Byte offset of the first character.
+    pub start: usize,
+    /// Byte offset one past the last character.
+    pub end: usize,
+    /// 1-based line of `start`.
+    pub line: usize,
+    /// 1-based column of `start`, counted in characters.
+    pub column: usize,
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Byte offsets of every line start, used to turn offsets into line/column pairs
+/// without rescanning the input for every element.
+struct LineIndex<'a> {
+    input: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(input: &'a str) -> Self {
+        let mut starts = vec![0];
+        starts.extend(input.match_indices('\n').map(|(i, _)| i + 1));
+        LineIndex { input, starts }
+    }
+
+    fn span(&self, start: usize, end: usize) -> Span {
+        let line = self.starts.partition_point(|&s| s <= start);
+        let column = self.input[self.starts[line - 1]..start].chars().count() + 1;
+
+        Span {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+}
+
+/// How serious a [`Diagnostic`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Input that could not be parsed and was skipped.
+    Error,
+    /// Input that was parsed, but only by guessing what was meant.
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A problem found while parsing, with the location it was found at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.span, self.severity, self.message)
+    }
+}
+
+/// The pairs [`parse_config_file_recovering`] managed to build, and what went wrong on the way.
+#[derive(Debug, Clone, Default)]
+pub struct ParseOutput {
+    pub pairs: Vec<ConfigPair>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl ParseOutput {
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error)
     }
 }
 
 pub fn parse_config_file(file: &str) -> Result<Vec<ConfigPair>, Error<Rule>> {
     let cfg = ConfigParser::parse(Rule::config, file)?;
+    let lines = LineIndex::new(file);
 
-    use pest::iterators::Pair;
-
-    fn parse_pair(pair: Pair<Rule>) -> ConfigPair {
-        match pair.as_rule() {
-            Rule::pair => {
-                let mut inner_rules = pair.into_inner();
-                let identifier = inner_rules.next().unwrap().as_str().to_owned();
-                let sign = inner_rules.next().unwrap().as_str().to_owned();
-                let value = parse_value(inner_rules.next().unwrap());
-                ConfigPair {
-                    identifier,
-                    sign,
-                    value,
-                }
+    let result: Vec<ConfigPair> = cfg
+        .filter(|pair| pair.as_rule() != Rule::EOI)
+        .map(|pair| parse_pair(pair, &lines, 0))
+        .collect();
+
+    Ok(result)
+}
+
+/// Parses as much of `file` as possible instead of giving up at the first syntax error.
+///
+/// A pair that fails to parse is skipped up to the end of its line, or up to the closing
+/// brace of the object it is in, and parsing continues from there. Objects that are broken
+/// somewhere inside keep the pairs before and after the broken one.
+pub fn parse_config_file_recovering(file: &str) -> ParseOutput {
+    let lines = LineIndex::new(file);
+    let mut output = ParseOutput::default();
+    let mut offset = 0;
+
+    output.pairs = parse_pairs_recovering(file, &lines, &mut offset, None, &mut output.diagnostics);
+
+    output
+}
+
+fn parse_pairs_recovering(
+    input: &str,
+    lines: &LineIndex,
+    offset: &mut usize,
+    open_brace: Option<Span>,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Vec<ConfigPair> {
+    let mut pairs = Vec::new();
+
+    loop {
+        *offset = skip_trivia(input, *offset);
+        let rest = &input[*offset..];
+
+        if rest.is_empty() {
+            if let Some(open_brace) = open_brace {
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Warning,
+                    message: "unclosed brace".to_owned(),
+                    span: open_brace,
+                });
             }
-            _ => unreachable!(),
+            return pairs;
         }
-    }
 
-    fn parse_value(pair: Pair<Rule>) -> ConfigValue {
-        match pair.as_rule() {
-            Rule::object => {
-                let mut inner_rules = pair.into_inner();
-                let mut object = Vec::new();
-                while let Some(pair) = inner_rules.next() {
-                    object.push(parse_pair(pair));
-                }
-                ConfigValue::Object(object)
-            }
-            Rule::array => {
-                let mut inner_rules = pair.into_inner();
-                let mut array = Vec::new();
-                while let Some(pair) = inner_rules.next() {
-                    array.push(parse_value(pair));
-                }
-                ConfigValue::Array(array)
+        if rest.starts_with('}') {
+            *offset += 1;
+            if open_brace.is_some() {
+                return pairs;
             }
-            Rule::string => {
-                ConfigValue::String(pair.into_inner().next().unwrap().as_str().to_owned())
+            diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                message: "unexpected closing brace".to_owned(),
+                span: lines.span(*offset - 1, *offset),
+            });
+            continue;
+        }
+
+        let error = match ConfigParser::parse(Rule::pair, rest) {
+            Ok(mut parsed) => {
+                let pair = parsed.next().unwrap();
+                let end = pair.as_span().end();
+                pairs.push(parse_pair(pair, lines, *offset));
+                *offset += end;
+                continue;
             }
-            Rule::number => ConfigValue::Number(pair.as_str().trim().parse().unwrap()),
-            Rule::identifier => ConfigValue::Identifier(pair.as_str().to_owned()),
-            Rule::date => {
-                let date: Vec<&str> = pair.as_str().split(".").collect();
+            Err(error) => error,
+        };
 
-                if date.len() < 3 {
-                    panic!("Invalid date format");
-                }
+        // The pair opens an object that is broken somewhere inside, so keep what can be
+        // parsed of its contents.
+        if let Ok(mut header) = ConfigParser::parse(Rule::header, rest) {
+            let header = header.next().unwrap();
+            let start = *offset;
+            let brace = lines.span(
+                start + header.as_span().end() - 1,
+                start + header.as_span().end(),
+            );
+            let mut inner_rules = header.into_inner();
+            let identifier = inner_rules.next().unwrap().as_str().to_owned();
+            let sign = inner_rules.next().unwrap().as_str().to_owned();
+
+            *offset = brace.end;
+            let object = parse_pairs_recovering(input, lines, offset, Some(brace), diagnostics);
+            let value_span = lines.span(brace.start, *offset);
+
+            pairs.push(ConfigPair {
+                identifier,
+                sign,
+                value: ConfigValue::Object(object),
+                span: lines.span(start, *offset),
+                value_span,
+            });
+            continue;
+        }
 
-                let year = date[0].parse().unwrap();
-                let month = date[1].parse().unwrap();
-                let day = date[2].parse().unwrap();
+        let (start, end) = match error.location {
+            InputLocation::Pos(pos) => (pos, pos),
+            InputLocation::Span(span) => span,
+        };
+        diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            message: error.variant.message().into_owned(),
+            span: lines.span(*offset + start, *offset + end),
+        });
 
-                ConfigValue::Date(year, month, day)
-            }
-            Rule::named => {
-                let mut inner_rules = pair.into_inner();
-                let name = inner_rules.next().unwrap().as_str().to_owned();
-                let mut values = Vec::new();
-                while let Some(pair) = inner_rules.next() {
-                    values.push(parse_value(pair));
-                }
-                ConfigValue::Named(name, values)
+        *offset = resync(input, *offset);
+    }
+}
+
+/// Skips whitespace and comments.
+fn skip_trivia(input: &str, mut offset: usize) -> usize {
+    loop {
+        let rest = &input[offset..];
+        let trimmed = rest.trim_start();
+        offset += rest.len() - trimmed.len();
+
+        if !trimmed.starts_with('#') {
+            return offset;
+        }
+        offset += trimmed.find('\n').unwrap_or(trimmed.len());
+    }
+}
+
+/// Finds where to continue after a broken pair: the start of the next line, or the next
+/// closing brace of the enclosing object, whichever comes first. Braces opened by the
+/// broken pair itself are skipped over.
+fn resync(input: &str, offset: usize) -> usize {
+    let mut depth = 0;
+    let mut in_string = false;
+
+    for (i, c) in input[offset..].char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '\n' if in_string => return offset + i + 1,
+            _ if in_string => {}
+            '{' => depth += 1,
+            '}' if depth == 0 => return offset + i,
+            '}' => depth -= 1,
+            '\n' if depth == 0 => return offset + i + 1,
+            _ => {}
+        }
+    }
+
+    input.len()
+}
+
+fn value_span(pair: &Pair<Rule>, lines: &LineIndex, base: usize) -> Span {
+    let span = pair.as_span();
+    // Numbers swallow the whitespace that terminates them.
+    let end = match pair.as_rule() {
+        Rule::number => span.start() + span.as_str().trim_end().len(),
+        _ => span.end(),
+    };
+    lines.span(base + span.start(), base + end)
+}
+
+/// Converts a parsed `pair` rule. Positions reported by pest are relative to the parsed
+/// text, which starts at byte `base` of the file.
+fn parse_pair(pair: Pair<Rule>, lines: &LineIndex, base: usize) -> ConfigPair {
+    match pair.as_rule() {
+        Rule::pair => {
+            let start = base + pair.as_span().start();
+            let mut inner_rules = pair.into_inner();
+            let identifier = inner_rules.next().unwrap().as_str().to_owned();
+            let sign = inner_rules.next().unwrap().as_str().to_owned();
+            let value_pair = inner_rules.next().unwrap();
+            let value_span = value_span(&value_pair, lines, base);
+            let value = parse_value(value_pair, lines, base);
+            ConfigPair {
+                identifier,
+                sign,
+                value,
+                span: lines.span(start, value_span.end),
+                value_span,
             }
-            _ => unreachable!(),
         }
+        _ => unreachable!(),
     }
+}
 
-    let result: Vec<ConfigPair> = cfg
-        .filter(|pair| pair.as_rule() != Rule::EOI)
-        .map(parse_pair)
-        .collect();
+fn parse_value(pair: Pair<Rule>, lines: &LineIndex, base: usize) -> ConfigValue {
+    match pair.as_rule() {
+        Rule::object => {
+            let object = pair
+                .into_inner()
+                .map(|pair| parse_pair(pair, lines, base))
+                .collect();
+            ConfigValue::Object(object)
+        }
+        Rule::array => {
+            let array = pair
+                .into_inner()
+                .map(|pair| parse_element(pair, lines, base))
+                .collect();
+            ConfigValue::Array(array)
+        }
+        Rule::string => ConfigValue::String(pair.into_inner().next().unwrap().as_str().to_owned()),
+        Rule::number => ConfigValue::Number(pair.as_str().trim().parse().unwrap()),
+        Rule::identifier => ConfigValue::Identifier(pair.as_str().to_owned()),
+        Rule::variable => {
+            ConfigValue::Variable(pair.into_inner().next().unwrap().as_str().to_owned())
+        }
+        Rule::math => ConfigValue::Math(pair.into_inner().next().unwrap().as_str().to_owned()),
+        Rule::date => {
+            let date: Vec<&str> = pair.as_str().split('.').collect();
 
-    Ok(result)
+            if date.len() < 3 {
+                panic!("Invalid date format");
+            }
+
+            let year = date[0].parse().unwrap();
+            let month = date[1].parse().unwrap();
+            let day = date[2].parse().unwrap();
+
+            ConfigValue::Date(year, month, day)
+        }
+        Rule::named => {
+            let mut inner_rules = pair.into_inner();
+            let name = inner_rules.next().unwrap().as_str().to_owned();
+            let values = inner_rules
+                .next()
+                .unwrap()
+                .into_inner()
+                .map(|pair| parse_element(pair, lines, base))
+                .collect();
+            ConfigValue::Named(name, values)
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn parse_element(pair: Pair<Rule>, lines: &LineIndex, base: usize) -> ConfigElement {
+    let span = value_span(&pair, lines, base);
+    ConfigElement {
+        value: parse_value(pair, lines, base),
+        span,
+    }
 }
 
 #[cfg(test)]
@@ -148,6 +437,7 @@ mod tests {
     use super::*;
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_parse_config_file() {
         let input = r#"
             key1 = "value1"
@@ -191,9 +481,9 @@ mod tests {
         assert_eq!(
             cfg[5].value,
             ConfigValue::Array(vec![
-                ConfigValue::Number(1.0),
-                ConfigValue::Number(2.0),
-                ConfigValue::Number(3.0)
+                ConfigValue::Number(1.0).into(),
+                ConfigValue::Number(2.0).into(),
+                ConfigValue::Number(3.0).into()
             ])
         );
 
@@ -201,40 +491,20 @@ mod tests {
         assert_eq!(
             cfg[6].value,
             ConfigValue::Object(vec![
-                ConfigPair {
-                    identifier: "key8".to_string(),
-                    sign: "=".to_string(),
-                    value: ConfigValue::String("value8".to_string())
-                },
-                ConfigPair {
-                    identifier: "key9".to_string(),
-                    sign: "=".to_string(),
-                    value: ConfigValue::Number(84.0)
-                },
-                ConfigPair {
-                    identifier: "key10".to_string(),
-                    sign: "=".to_string(),
-                    value: ConfigValue::Identifier("yes".to_string())
-                },
-                ConfigPair {
-                    identifier: "key11".to_string(),
-                    sign: "=".to_string(),
-                    value: ConfigValue::Identifier("no".to_string())
-                },
-                ConfigPair {
-                    identifier: "key12".to_string(),
-                    sign: "=".to_string(),
-                    value: ConfigValue::Number(3.41)
-                },
-                ConfigPair {
-                    identifier: "key13".to_string(),
-                    sign: "=".to_string(),
-                    value: ConfigValue::Array(vec![
-                        ConfigValue::Number(4.0),
-                        ConfigValue::Number(5.0),
-                        ConfigValue::Number(6.0)
+                ConfigPair::new("key8", "=", ConfigValue::String("value8".to_string())),
+                ConfigPair::new("key9", "=", ConfigValue::Number(84.0)),
+                ConfigPair::new("key10", "=", ConfigValue::Identifier("yes".to_string())),
+                ConfigPair::new("key11", "=", ConfigValue::Identifier("no".to_string())),
+                ConfigPair::new("key12", "=", ConfigValue::Number(3.41)),
+                ConfigPair::new(
+                    "key13",
+                    "=",
+                    ConfigValue::Array(vec![
+                        ConfigValue::Number(4.0).into(),
+                        ConfigValue::Number(5.0).into(),
+                        ConfigValue::Number(6.0).into()
                     ])
-                }
+                )
             ])
         );
 
@@ -271,6 +541,26 @@ mod tests {
         assert!(cfg.is_err());
     }
 
+    #[test]
+    fn test_parse_config_file_variables() {
+        let input = "@my_var = 10\nkey1 = @my_var\nkey2 = @[ my_var * 2 ]\nkey3 = { @my_var }\nkey4 = @my_var.x\n";
+
+        let cfg = parse_config_file(input).unwrap();
+        assert_eq!(cfg[0].identifier, "@my_var");
+        assert_eq!(cfg[1].value, ConfigValue::Variable("my_var".to_owned()));
+        assert_eq!(cfg[2].value, ConfigValue::Math(" my_var * 2 ".to_owned()));
+        assert_eq!(cfg[2].value.to_string(), "@[ my_var * 2 ]");
+        assert_eq!(cfg[2].value_span.end, input.find(" ]\n").unwrap() + 2);
+        assert_eq!(
+            cfg[3].value,
+            ConfigValue::Array(vec![ConfigValue::Variable("my_var".to_owned()).into()])
+        );
+        assert_eq!(
+            cfg[4].value,
+            ConfigValue::Identifier("@my_var.x".to_owned())
+        );
+    }
+
     #[test]
     fn test_parse_config_file_empty() {
         let input = r#""#;
@@ -307,15 +597,15 @@ mod tests {
         assert_eq!(cfg[0].identifier, "key1");
         assert_eq!(
             cfg[0].value,
-            ConfigValue::Object(vec![ConfigPair {
-                identifier: "key2".to_string(),
-                sign: "=".to_string(),
-                value: ConfigValue::Object(vec![ConfigPair {
-                    identifier: "key3".to_string(),
-                    sign: "=".to_string(),
-                    value: ConfigValue::String("value3".to_string())
-                }])
-            }])
+            ConfigValue::Object(vec![ConfigPair::new(
+                "key2",
+                "=",
+                ConfigValue::Object(vec![ConfigPair::new(
+                    "key3",
+                    "=",
+                    ConfigValue::String("value3".to_string())
+                )])
+            )])
         );
     }
 
@@ -334,6 +624,162 @@ mod tests {
         assert_eq!(cfg[0].value, ConfigValue::String("value1".to_string()));
     }
 
+    #[test]
+    fn test_parse_config_file_spans() {
+        let input = "key1 = 42\nkey2 = {\n\tkey3 = \"Qu\u{e9}bec\" key4 = yes\n}\n";
+
+        let cfg = parse_config_file(input).unwrap();
+
+        assert_eq!(
+            cfg[0].span,
+            Span {
+                start: 0,
+                end: 9,
+                line: 1,
+                column: 1
+            }
+        );
+        assert_eq!(
+            cfg[0].value_span,
+            Span {
+                start: 7,
+                end: 9,
+                line: 1,
+                column: 8
+            }
+        );
+
+        assert_eq!(cfg[1].span.line, 2);
+        assert_eq!(cfg[1].span.end, input.len() - 1);
+
+        let ConfigValue::Object(ref children) = cfg[1].value else {
+            panic!("expected object");
+        };
+        assert_eq!(children[0].span.line, 3);
+        assert_eq!(children[0].span.column, 2);
+        assert_eq!(
+            &input[children[0].value_span.start..children[0].value_span.end],
+            "\"Qu\u{e9}bec\""
+        );
+        assert_eq!(children[1].span.line, 3);
+        assert_eq!(children[1].span.column, 18);
+    }
+
+    #[test]
+    fn test_parse_config_file_element_spans() {
+        let input = "key1 = { 1 \"two\" }\ncolor = rgb {\n\t255 0 0\n}\n";
+
+        let cfg = parse_config_file(input).unwrap();
+
+        let ConfigValue::Array(ref array) = cfg[0].value else {
+            panic!("expected array");
+        };
+        assert_eq!(
+            array[0].span,
+            Span {
+                start: 9,
+                end: 10,
+                line: 1,
+                column: 10
+            }
+        );
+        assert_eq!(&input[array[1].span.start..array[1].span.end], "\"two\"");
+
+        let ConfigValue::Named(_, ref values) = cfg[1].value else {
+            panic!("expected named block");
+        };
+        assert_eq!(values[0].value, ConfigValue::Number(255.0));
+        assert_eq!(values[0].span.line, 3);
+        assert_eq!(values[0].span.column, 2);
+        assert_eq!(&input[values[2].span.start..values[2].span.end], "0");
+    }
+
+    #[test]
+    fn test_parse_config_file_recovering() {
+        let input = r#"
+            key1 = "value1"
+            key2 = = 42
+            key3 = {
+                key4 = yes
+                key5 = "unterminated
+                key6 = no
+            }
+            key7 = 3
+        "#;
+
+        let output = parse_config_file_recovering(input);
+
+        assert!(output.has_errors());
+        assert_eq!(output.pairs.len(), 3);
+        assert_eq!(output.pairs[0].identifier, "key1");
+        assert_eq!(output.pairs[1].identifier, "key3");
+        assert_eq!(output.pairs[2].identifier, "key7");
+        assert_eq!(output.pairs[2].value, ConfigValue::Number(3.0));
+
+        let ConfigValue::Object(ref children) = output.pairs[1].value else {
+            panic!("expected object");
+        };
+        assert_eq!(
+            children[0],
+            ConfigPair::new("key4", "=", ConfigValue::Identifier("yes".to_string()))
+        );
+        assert_eq!(
+            children[1],
+            ConfigPair::new("key6", "=", ConfigValue::Identifier("no".to_string()))
+        );
+        assert_eq!(children.len(), 2);
+        assert_eq!(output.pairs[1].span.line, 4);
+
+        assert_eq!(output.diagnostics.len(), 2);
+        assert_eq!(output.diagnostics[0].severity, Severity::Error);
+        assert_eq!(output.diagnostics[0].span.line, 3);
+        assert_eq!(output.diagnostics[1].span.line, 6);
+    }
+
+    #[test]
+    fn test_parse_config_file_recovering_braces() {
+        let input = "key1 = 1\n}\nkey2 = {\n\tkey3 = 2\n";
+
+        let output = parse_config_file_recovering(input);
+
+        assert!(!output.has_errors());
+        assert_eq!(
+            output.pairs,
+            vec![
+                ConfigPair::new("key1", "=", ConfigValue::Number(1.0)),
+                ConfigPair::new(
+                    "key2",
+                    "=",
+                    ConfigValue::Object(vec![ConfigPair::new(
+                        "key3",
+                        "=",
+                        ConfigValue::Number(2.0)
+                    )])
+                ),
+            ]
+        );
+
+        assert_eq!(output.diagnostics.len(), 2);
+        assert_eq!(output.diagnostics[0].message, "unexpected closing brace");
+        assert_eq!(output.diagnostics[0].span.line, 2);
+        assert_eq!(output.diagnostics[1].message, "unclosed brace");
+        assert_eq!(output.diagnostics[1].span.line, 3);
+        assert_eq!(output.diagnostics[1].span.column, 8);
+    }
+
+    #[test]
+    fn test_parse_config_file_recovering_valid() {
+        let input = r#"
+            key1 = { key2 = "value2" }
+            key3 = { 1 2 3 }
+        "#;
+
+        let output = parse_config_file_recovering(input);
+
+        assert!(output.diagnostics.is_empty());
+        assert_eq!(output.pairs, parse_config_file(input).unwrap());
+    }
+
     #[test]
     fn test_configvalue_display() {
         let value = ConfigValue::String("value1".to_string());
@@ -346,51 +792,67 @@ mod tests {
         assert_eq!(format!("{}", value), "identifier_123");
 
         let value = ConfigValue::Array(vec![
-            ConfigValue::Number(1.0),
-            ConfigValue::Number(2.0),
-            ConfigValue::Number(3.0),
+            ConfigValue::Number(1.0).into(),
+            ConfigValue::Number(2.0).into(),
+            ConfigValue::Number(3.0).into(),
         ]);
         assert_eq!(format!("{}", value), "{ 1 2 3 }");
 
         let value = ConfigValue::Object(vec![
-            ConfigPair {
-                identifier: "key8".to_string(),
-                sign: "=".to_string(),
-                value: ConfigValue::String("value8".to_string()),
-            },
-            ConfigPair {
-                identifier: "key9".to_string(),
-                sign: "=".to_string(),
-                value: ConfigValue::Number(84.0),
-            },
-            ConfigPair {
-                identifier: "key12".to_string(),
-                sign: "=".to_string(),
-                value: ConfigValue::Number(3.41),
-            },
-            ConfigPair {
-                identifier: "key13".to_string(),
-                sign: "=".to_string(),
-                value: ConfigValue::Array(vec![
-                    ConfigValue::Number(4.0),
-                    ConfigValue::Number(5.0),
-                    ConfigValue::Number(6.0),
+            ConfigPair::new("key8", "=", ConfigValue::String("value8".to_string())),
+            ConfigPair::new("key9", "=", ConfigValue::Number(84.0)),
+            ConfigPair::new("key12", "=", ConfigValue::Number(3.41)),
+            ConfigPair::new(
+                "key13",
+                "=",
+                ConfigValue::Array(vec![
+                    ConfigValue::Number(4.0).into(),
+                    ConfigValue::Number(5.0).into(),
+                    ConfigValue::Number(6.0).into(),
                 ]),
-            },
+            ),
         ]);
         assert_eq!(
             format!("{}", value),
-            "{\n   key8 = \"value8\"\n   key9 = 84\n   key12 = 3.41\n   key13 = { 4 5 6 }\n}"
+            "{\n\tkey8 = \"value8\"\n\tkey9 = 84\n\tkey12 = 3.41\n\tkey13 = { 4 5 6 }\n}"
+        );
+    }
+
+    #[test]
+    fn test_configvalue_display_nested() {
+        let value = ConfigValue::Object(vec![
+            ConfigPair::new(
+                "key1",
+                "=",
+                ConfigValue::Object(vec![ConfigPair::new("key2", "<", ConfigValue::Number(1.0))]),
+            ),
+            ConfigPair::new(
+                "color",
+                "=",
+                ConfigValue::Named(
+                    "rgb".to_string(),
+                    vec![
+                        ConfigValue::Number(255.0).into(),
+                        ConfigValue::Number(0.0).into(),
+                        ConfigValue::Number(0.0).into(),
+                    ],
+                ),
+            ),
+        ]);
+
+        assert_eq!(
+            format!("{}", value),
+            "{\n\tkey1 = {\n\t\tkey2 < 1\n\t}\n\tcolor = rgb { 255 0 0 }\n}"
+        );
+        assert_eq!(
+            parse_config_file(&format!("key = {}\n", value)).unwrap()[0].value,
+            value
         );
     }
 
     #[test]
     fn test_configpair_display() {
-        let pair = ConfigPair {
-            identifier: "key1".to_string(),
-            sign: "=".to_string(),
-            value: ConfigValue::String("value1".to_string()),
-        };
+        let pair = ConfigPair::new("key1", "=", ConfigValue::String("value1".to_string()));
         assert_eq!(format!("{}", pair), "key1 = \"value1\"\n");
     }
 }
@@ -0,0 +1,527 @@
+//! What differs between the games: where their script is, and how it is written.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::file::{Encoding, SourceFile};
+use crate::mods::{MergeRule, MergeRules};
+use crate::parser::{ConfigPair, ConfigValue, Diagnostic, Severity, Span};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameId {
+    Eu4,
+    Hoi4,
+    Ck3,
+    Stellaris,
+    Victoria3,
+    Imperator,
+}
+
+impl GameId {
+    pub const ALL: [GameId; 6] = [
+        GameId::Eu4,
+        GameId::Hoi4,
+        GameId::Ck3,
+        GameId::Stellaris,
+        GameId::Victoria3,
+        GameId::Imperator,
+    ];
+
+    /// The `gameId` in the launcher settings.
+    fn launcher_id(self) -> &'static str {
+        match self {
+            GameId::Eu4 => "eu4",
+            GameId::Hoi4 => "hoi4",
+            GameId::Ck3 => "ck3",
+            GameId::Stellaris => "stellaris",
+            GameId::Victoria3 => "victoria3",
+            GameId::Imperator => "imperator_rome",
+        }
+    }
+
+    /// A folder only this game has, relative to its script folder.
+    fn marker(self) -> &'static str {
+        match self {
+            GameId::Eu4 => "missions",
+            GameId::Hoi4 => "common/national_focus",
+            GameId::Ck3 => "common/landed_titles",
+            GameId::Stellaris => "common/species_classes",
+            GameId::Victoria3 => "common/production_methods",
+            GameId::Imperator => "common/deities",
+        }
+    }
+
+    /// Whether the game is built on the newer engine, which keeps its script in a `game`
+    /// folder and understands `?=`.
+    fn is_jomini(self) -> bool {
+        matches!(self, GameId::Ck3 | GameId::Victoria3 | GameId::Imperator)
+    }
+
+    /// The encoding the game reads script in. Only Europa Universalis IV still reads
+    /// Windows-1252; the others read UTF-8, with or without a byte order mark.
+    fn encoding(self) -> Encoding {
+        match self {
+            GameId::Eu4 => Encoding::Windows1252,
+            _ => Encoding::Utf8,
+        }
+    }
+}
+
+impl Display for GameId {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            GameId::Eu4 => write!(f, "Europa Universalis IV"),
+            GameId::Hoi4 => write!(f, "Hearts of Iron IV"),
+            GameId::Ck3 => write!(f, "Crusader Kings III"),
+            GameId::Stellaris => write!(f, "Stellaris"),
+            GameId::Victoria3 => write!(f, "Victoria 3"),
+            GameId::Imperator => write!(f, "Imperator: Rome"),
+        }
+    }
+}
+
+/// What a file is for, going by its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum FileCategory {
+    Script,
+    /// Interface layouts, such as `.gui` files.
+    Interface,
+    /// Sprites and models, such as `.gfx` and `.asset` files.
+    Graphics,
+    Sound,
+    /// Mod descriptors.
+    Mods,
+    /// Localisation files, which are not script. See [`crate::localisation`].
+    Localisation,
+}
+
+impl FileCategory {
+    pub const ALL: [FileCategory; 6] = [
+        FileCategory::Script,
+        FileCategory::Interface,
+        FileCategory::Graphics,
+        FileCategory::Sound,
+        FileCategory::Mods,
+        FileCategory::Localisation,
+    ];
+}
+
+impl Display for FileCategory {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            FileCategory::Script => write!(f, "Script"),
+            FileCategory::Interface => write!(f, "Interface"),
+            FileCategory::Graphics => write!(f, "Graphics"),
+            FileCategory::Sound => write!(f, "Sound"),
+            FileCategory::Mods => write!(f, "Mods"),
+            FileCategory::Localisation => write!(f, "Localisation"),
+        }
+    }
+}
+
+/// The extensions of the files to load, each with the category of those files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extensions {
+    /// Keyed by lowercase extension, without the dot.
+    categories: BTreeMap<String, FileCategory>,
+}
+
+impl Extensions {
+    /// No extensions, so no files are loaded.
+    pub fn new() -> Self {
+        Extensions {
+            categories: BTreeMap::new(),
+        }
+    }
+
+    /// Loads files with `extension`, with or without the dot, as `category`.
+    pub fn insert(&mut self, extension: &str, category: FileCategory) {
+        self.categories.insert(
+            extension.trim_start_matches('.').to_ascii_lowercase(),
+            category,
+        );
+    }
+
+    pub fn remove(&mut self, extension: &str) {
+        self.categories
+            .remove(&extension.trim_start_matches('.').to_ascii_lowercase());
+    }
+
+    /// The category of the file at `path`, or `None` when it is not loaded.
+    pub fn category(&self, path: &Path) -> Option<FileCategory> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        self.categories.get(&extension).copied()
+    }
+
+    /// The extensions `game` writes in script. The newer engine has no sprite files, as its
+    /// `.gui` files refer to textures directly, and no `.sfx` files.
+    pub fn for_game(game: GameId) -> Self {
+        let mut extensions = Extensions::default();
+        if game.is_jomini() {
+            extensions.remove("gfx");
+            extensions.remove("sfx");
+        }
+        extensions
+    }
+
+    /// Every extension with its category, sorted by extension.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, FileCategory)> {
+        self.categories
+            .iter()
+            .map(|(extension, category)| (extension.as_str(), *category))
+    }
+}
+
+impl Default for Extensions {
+    /// Every extension written in script: `.txt`, `.gui`, `.gfx`, `.asset`, `.sfx` and
+    /// `.mod`.
+    fn default() -> Self {
+        let mut extensions = Extensions::new();
+        extensions.insert("txt", FileCategory::Script);
+        extensions.insert("gui", FileCategory::Interface);
+        extensions.insert("gfx", FileCategory::Graphics);
+        extensions.insert("asset", FileCategory::Graphics);
+        extensions.insert("sfx", FileCategory::Sound);
+        extensions.insert("mod", FileCategory::Mods);
+        extensions
+    }
+}
+
+/// Script features that not every game understands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dialect {
+    /// Whether `?=`, which compares only when the left side exists, is understood.
+    pub conditional_equals: bool,
+}
+
+impl Default for Dialect {
+    /// Everything is understood.
+    fn default() -> Self {
+        Dialect {
+            conditional_equals: true,
+        }
+    }
+}
+
+impl Dialect {
+    /// Warns about every use of a feature the dialect does not have.
+    pub fn check(&self, pairs: &[ConfigPair]) -> Vec<Diagnostic> {
+        fn walk(dialect: &Dialect, pairs: &[ConfigPair], diagnostics: &mut Vec<Diagnostic>) {
+            for pair in pairs {
+                if pair.sign == "?=" && !dialect.conditional_equals {
+                    diagnostics.push(Diagnostic {
+                        severity: Severity::Warning,
+                        message: "`?=` is not supported by this game".to_owned(),
+                        span: pair.span,
+                    });
+                }
+
+                walk_value(dialect, &pair.value, diagnostics);
+            }
+        }
+
+        fn walk_value(dialect: &Dialect, value: &ConfigValue, diagnostics: &mut Vec<Diagnostic>) {
+            match value {
+                ConfigValue::Object(children) => walk(dialect, children, diagnostics),
+                ConfigValue::Array(elements) | ConfigValue::Named(_, elements) => {
+                    for element in elements {
+                        walk_value(dialect, &element.value, diagnostics);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut diagnostics = Vec::new();
+        walk(self, pairs, &mut diagnostics);
+        diagnostics
+    }
+}
+
+/// Where a game keeps its script and how to read it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    /// `None` for a folder that is not a known game, which is read as generously as
+    /// possible.
+    pub game: Option<GameId>,
+    /// Folders to scan, relative to the script folder. Empty to scan every folder.
+    pub folders: Vec<String>,
+    /// Extensions of the files to load.
+    pub extensions: Extensions,
+    /// The encoding the game reads script in. Files are still decoded by their bytes, see
+    /// [`crate::file::decode`], but get a warning when the game would read them
+    /// differently. `None` to accept any encoding.
+    pub encoding: Option<Encoding>,
+    pub dialect: Dialect,
+    pub merge_rules: MergeRules,
+}
+
+impl Default for Profile {
+    /// Scans every file written in script and understands every feature.
+    fn default() -> Self {
+        Profile {
+            game: None,
+            folders: Vec::new(),
+            extensions: Extensions::default(),
+            encoding: None,
+            dialect: Dialect::default(),
+            merge_rules: MergeRules::default(),
+        }
+    }
+}
+
+impl Profile {
+    pub fn new(game: GameId) -> Self {
+        let folders: &[&str] = match game {
+            GameId::Eu4 => &[
+                "common",
+                "customizable_localization",
+                "decisions",
+                "events",
+                "gfx",
+                "history",
+                "interface",
+                "map",
+                "missions",
+                "sound",
+            ],
+            GameId::Hoi4 => &[
+                "common",
+                "events",
+                "gfx",
+                "history",
+                "interface",
+                "map",
+                "sound",
+            ],
+            GameId::Ck3 | GameId::Victoria3 => &[
+                "common", "events", "gfx", "gui", "history", "map_data", "sound",
+            ],
+            GameId::Stellaris => &[
+                "common",
+                "events",
+                "gfx",
+                "interface",
+                "map",
+                "prescripted_countries",
+                "sound",
+            ],
+            GameId::Imperator => &[
+                "common",
+                "decisions",
+                "events",
+                "gfx",
+                "gui",
+                "map_data",
+                "setup",
+                "sound",
+            ],
+        };
+
+        let mut merge_rules = MergeRules::default();
+        match game {
+            // Most of Stellaris keeps the first definition of an object.
+            GameId::Stellaris => merge_rules.set("common", MergeRule::FirstWins),
+            _ if game.is_jomini() => merge_rules.set("common/defines", MergeRule::Merge),
+            _ => {}
+        }
+
+        Profile {
+            game: Some(game),
+            folders: folders.iter().map(|folder| folder.to_string()).collect(),
+            extensions: Extensions::for_game(game),
+            encoding: Some(game.encoding()),
+            dialect: Dialect {
+                conditional_equals: game.is_jomini(),
+            },
+            merge_rules,
+        }
+    }
+
+    /// Detects the game in the folder at `path`, which may be the install folder or the
+    /// folder with the script. Looks at the launcher settings first, and then for folders
+    /// only one of the games has. Returns the default profile for anything else.
+    pub fn detect(path: &Path) -> Self {
+        launcher_game(path)
+            .or_else(|| {
+                GameId::ALL.into_iter().find(|game| {
+                    path.join(game.marker()).is_dir()
+                        || path.join("game").join(game.marker()).is_dir()
+                })
+            })
+            .map_or_else(Profile::default, Profile::new)
+    }
+
+    /// Warns when `source` is in an encoding the game reads differently from how it was
+    /// decoded. Text that is all ASCII reads the same in every encoding.
+    pub fn check_encoding(&self, source: &SourceFile) -> Option<Diagnostic> {
+        let is_utf8 = |encoding| encoding != Encoding::Windows1252;
+        let expected = self.encoding?;
+
+        if source.text.is_ascii() || is_utf8(source.encoding) == is_utf8(expected) {
+            return None;
+        }
+        Some(Diagnostic {
+            severity: Severity::Warning,
+            message: format!(
+                "the file is {}, but {} reads script as {}",
+                source.encoding, self, expected
+            ),
+            span: Span {
+                line: 1,
+                column: 1,
+                ..Span::default()
+            },
+        })
+    }
+
+    /// The folder with the script of the game installed at `path`.
+    pub fn script_root(&self, path: &Path) -> PathBuf {
+        let game = path.join("game");
+
+        if self.game.is_some_and(GameId::is_jomini) && game.is_dir() {
+            game
+        } else {
+            path.to_owned()
+        }
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.game {
+            Some(game) => write!(f, "{}", game),
+            None => write!(f, "Unknown game"),
+        }
+    }
+}
+
+/// The game named in the launcher settings of the install folder at `path`.
+fn launcher_game(path: &Path) -> Option<GameId> {
+    let text = ["launcher-settings.json", "launcher/launcher-settings.json"]
+        .iter()
+        .find_map(|settings| fs::read_to_string(path.join(settings)).ok())?;
+    let settings: Value = serde_json::from_str(&text).ok()?;
+    let id = settings.get("gameId")?.as_str()?;
+
+    GameId::ALL
+        .into_iter()
+        .find(|game| game.launcher_id().eq_ignore_ascii_case(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::decode;
+    use crate::parser::parse_config_file;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_detect() {
+        let dir = TempDir::new("detect");
+        let root = dir.path();
+        fs::create_dir_all(root.join("eu4/missions")).unwrap();
+        fs::create_dir_all(root.join("ck3/game/common/landed_titles")).unwrap();
+        fs::create_dir_all(root.join("vic3/launcher")).unwrap();
+        fs::create_dir_all(root.join("vic3/game")).unwrap();
+        fs::create_dir_all(root.join("other/common")).unwrap();
+        fs::write(
+            root.join("vic3/launcher/launcher-settings.json"),
+            r#"{ "gameId": "victoria3", "version": "1.0" }"#,
+        )
+        .unwrap();
+
+        let eu4 = Profile::detect(&root.join("eu4"));
+        let ck3 = Profile::detect(&root.join("ck3"));
+        let ck3_script = Profile::detect(&root.join("ck3/game"));
+        let vic3 = Profile::detect(&root.join("vic3"));
+        let other = Profile::detect(&root.join("other"));
+        let script_roots = [
+            eu4.script_root(&root.join("eu4")),
+            ck3.script_root(&root.join("ck3")),
+        ];
+
+        assert_eq!(eu4.game, Some(GameId::Eu4));
+        assert_eq!(ck3.game, Some(GameId::Ck3));
+        assert_eq!(ck3_script.game, Some(GameId::Ck3));
+        assert_eq!(vic3.game, Some(GameId::Victoria3));
+        assert_eq!(other, Profile::default());
+        assert_eq!(script_roots, [root.join("eu4"), root.join("ck3/game")]);
+        assert_eq!(vic3.to_string(), "Victoria 3");
+    }
+
+    #[test]
+    fn test_extensions() {
+        let mut extensions = Extensions::default();
+        extensions.insert(".YML", FileCategory::Script);
+        extensions.remove("mod");
+
+        assert_eq!(
+            extensions.category(Path::new("interface/a.gui")),
+            Some(FileCategory::Interface)
+        );
+        assert_eq!(
+            extensions.category(Path::new("gfx/models/a.ASSET")),
+            Some(FileCategory::Graphics)
+        );
+        assert_eq!(
+            extensions.category(Path::new("localisation/a.yml")),
+            Some(FileCategory::Script)
+        );
+        assert_eq!(extensions.category(Path::new("descriptor.mod")), None);
+        assert_eq!(extensions.category(Path::new("README")), None);
+        assert_eq!(extensions.iter().count(), 6);
+
+        let eu4 = Profile::new(GameId::Eu4).extensions;
+        let ck3 = Profile::new(GameId::Ck3).extensions;
+        assert_eq!(
+            eu4.category(Path::new("interface/a.gfx")),
+            Some(FileCategory::Graphics)
+        );
+        assert_eq!(ck3.category(Path::new("interface/a.gfx")), None);
+        assert_eq!(
+            ck3.category(Path::new("gui/a.gui")),
+            Some(FileCategory::Interface)
+        );
+    }
+
+    #[test]
+    fn test_check_encoding() {
+        let eu4 = Profile::new(GameId::Eu4);
+        let ck3 = Profile::new(GameId::Ck3);
+        let utf8 = decode("name = \"Z\u{fc}rich\"\n".as_bytes());
+        let bom = decode(b"\xEF\xBB\xBFname = \"Z\xC3\xBCrich\"\n");
+        let windows_1252 = decode(b"name = \"Z\xFCrich\"\n");
+        let ascii = decode(b"name = \"Zurich\"\n");
+
+        assert_eq!(windows_1252.encoding, Encoding::Windows1252);
+        assert!(eu4.check_encoding(&windows_1252).is_none());
+        assert!(eu4.check_encoding(&ascii).is_none());
+        assert_eq!(
+            eu4.check_encoding(&utf8).map(|d| d.to_string()),
+            Some(
+                "1:1: warning: the file is UTF-8, but Europa Universalis IV reads script as Windows-1252"
+                    .to_owned()
+            )
+        );
+        assert!(ck3.check_encoding(&utf8).is_none());
+        assert!(ck3.check_encoding(&bom).is_none());
+        assert!(ck3.check_encoding(&windows_1252).is_some());
+        assert!(Profile::default().check_encoding(&windows_1252).is_none());
+    }
+
+    #[test]
+    fn test_dialect() {
+        let pairs = parse_config_file("a = { b ?= yes }\nc ?= 1\nlist = { { a ?= b } }\n").unwrap();
+
+        assert!(Profile::new(GameId::Ck3).dialect.check(&pairs).is_empty());
+
+        let diagnostics = Profile::new(GameId::Eu4).dialect.check(&pairs);
+        let lines: Vec<usize> = diagnostics.iter().map(|d| d.span.line).collect();
+        assert_eq!(lines, [1, 2, 3]);
+    }
+}
@@ -0,0 +1,344 @@
+//! A small path language for selecting pairs, in the spirit of XPath.
+//!
+//! A query is a list of steps separated by `/`, each selecting pairs among the children
+//! of the pairs selected by the step before:
+//!
+//! | Step                  | Selects                                                 |
+//! |-----------------------|---------------------------------------------------------|
+//! | `tag`, `"a key"`      | pairs with that key                                     |
+//! | `*`                   | every pair                                              |
+//! | `**`                  | the current pairs and all of their descendants          |
+//! | `step[key]`           | pairs whose block has a `key`                           |
+//! | `step[key = value]`   | pairs whose block has a `key` with that value           |
+//! | `step[. = value]`     | pairs whose own value is `value`                        |
+//!
+//! Predicates can use `=`, `!=`, `<`, `>`, `<=` and `>=`, and several can follow one step.
+//! Values compare as numbers when both sides are numbers, and as text otherwise; ordering
+//! only applies to numbers. For example, `technologies/*/allow/tag` lists the tags allowed
+//! by each technology, and `*[monarch_power = ADM]/cost` the cost of every pair with ADM
+//! as its monarch power.
+
+use std::collections::HashMap;
+
+use pest::error::Error;
+use pest::iterators::Pair;
+use pest::Parser;
+use pest_derive::Parser;
+
+use crate::game::ParsedFile;
+use crate::parser::{ConfigPair, ConfigValue};
+
+#[derive(Parser)]
+#[grammar = "query.pest"]
+struct QueryParser;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Descendants,
+    Select {
+        /// `None` for `*`.
+        key: Option<String>,
+        predicates: Vec<Predicate>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Predicate {
+    /// `None` for `.`, the pair itself.
+    key: Option<String>,
+    comparison: Option<(Op, String)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessOrEqual,
+    GreaterOrEqual,
+}
+
+/// A pair selected by a query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryMatch<'a> {
+    /// Keys leading to the pair, the pair's own key last.
+    pub path: Vec<String>,
+    pub pair: &'a ConfigPair,
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Query, Error<Rule>> {
+        let query = QueryParser::parse(Rule::query, query)?.next().unwrap();
+
+        let steps = query
+            .into_inner()
+            .filter(|step| step.as_rule() == Rule::step)
+            .map(parse_step)
+            .collect();
+
+        Ok(Query { steps })
+    }
+
+    /// Selects pairs from `pairs`, in source order.
+    pub fn select<'a>(&self, pairs: &'a [ConfigPair]) -> Vec<QueryMatch<'a>> {
+        let mut matches = Vec::new();
+        select(pairs, &self.steps, &mut Vec::new(), &mut matches);
+        matches
+    }
+}
+
+/// Runs `query` on every file, returning matches sorted by file and then position.
+pub fn query_game<'a>(
+    files: &'a HashMap<String, ParsedFile>,
+    query: &Query,
+) -> Vec<(&'a str, QueryMatch<'a>)> {
+    let mut matches: Vec<(&str, QueryMatch)> = files
+        .iter()
+        .flat_map(|(name, file)| {
+            query
+                .select(&file.pairs)
+                .into_iter()
+                .map(move |found| (name.as_str(), found))
+        })
+        .collect();
+
+    matches.sort_by(|(a, a_match), (b, b_match)| {
+        a.cmp(b)
+            .then(a_match.pair.span.start.cmp(&b_match.pair.span.start))
+    });
+    matches
+}
+
+fn parse_step(step: Pair<Rule>) -> Step {
+    let mut inner = step.into_inner();
+    let selector = inner.next().unwrap();
+
+    let key = match selector.as_rule() {
+        Rule::descendants => return Step::Descendants,
+        Rule::wildcard => None,
+        _ => Some(unquote(selector.as_str())),
+    };
+
+    Step::Select {
+        key,
+        predicates: inner.map(parse_predicate).collect(),
+    }
+}
+
+fn parse_predicate(predicate: Pair<Rule>) -> Predicate {
+    let mut inner = predicate.into_inner();
+    let subject = inner.next().unwrap();
+
+    let key = match subject.as_rule() {
+        Rule::current => None,
+        _ => Some(unquote(subject.as_str())),
+    };
+
+    let comparison = inner.next().map(|op| {
+        let op = match op.as_str() {
+            "=" => Op::Equal,
+            "!=" => Op::NotEqual,
+            "<" => Op::Less,
+            ">" => Op::Greater,
+            "<=" => Op::LessOrEqual,
+            _ => Op::GreaterOrEqual,
+        };
+        (op, unquote(inner.next().unwrap().as_str()))
+    });
+
+    Predicate { key, comparison }
+}
+
+fn unquote(text: &str) -> String {
+    strip_quotes(text).to_owned()
+}
+
+fn strip_quotes(text: &str) -> &str {
+    text.strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .unwrap_or(text)
+}
+
+fn select<'a>(
+    pairs: &'a [ConfigPair],
+    steps: &[Step],
+    path: &mut Vec<String>,
+    matches: &mut Vec<QueryMatch<'a>>,
+) {
+    let Some((step, rest)) = steps.split_first() else {
+        return;
+    };
+
+    match step {
+        Step::Descendants => {
+            select(pairs, rest, path, matches);
+
+            for pair in pairs {
+                path.push(pair.identifier.clone());
+
+                if rest.is_empty() {
+                    matches.push(QueryMatch {
+                        path: path.clone(),
+                        pair,
+                    });
+                }
+                if let ConfigValue::Object(children) = &pair.value {
+                    select(children, steps, path, matches);
+                }
+
+                path.pop();
+            }
+        }
+        Step::Select { key, predicates } => {
+            for pair in pairs {
+                if key
+                    .as_ref()
+                    .is_some_and(|key| key != strip_quotes(&pair.identifier))
+                    || !predicates.iter().all(|predicate| predicate.holds(pair))
+                {
+                    continue;
+                }
+
+                path.push(pair.identifier.clone());
+
+                if rest.is_empty() {
+                    matches.push(QueryMatch {
+                        path: path.clone(),
+                        pair,
+                    });
+                } else if let ConfigValue::Object(children) = &pair.value {
+                    select(children, rest, path, matches);
+                }
+
+                path.pop();
+            }
+        }
+    }
+}
+
+impl Predicate {
+    fn holds(&self, pair: &ConfigPair) -> bool {
+        let Some(key) = &self.key else {
+            return self
+                .comparison
+                .as_ref()
+                .is_none_or(|(op, literal)| compare(&pair.value, *op, literal));
+        };
+
+        let ConfigValue::Object(children) = &pair.value else {
+            return false;
+        };
+
+        children
+            .iter()
+            .filter(|child| strip_quotes(&child.identifier) == key)
+            .any(|child| {
+                self.comparison
+                    .as_ref()
+                    .is_none_or(|(op, literal)| compare(&child.value, *op, literal))
+            })
+    }
+}
+
+fn compare(value: &ConfigValue, op: Op, literal: &str) -> bool {
+    let text = match value {
+        ConfigValue::String(text) | ConfigValue::Identifier(text) => text.clone(),
+        ConfigValue::Object(_) => return false,
+        value => value.to_string(),
+    };
+
+    if let (Ok(a), Ok(b)) = (text.parse::<f64>(), literal.parse::<f64>()) {
+        return match op {
+            Op::Equal => a == b,
+            Op::NotEqual => a != b,
+            Op::Less => a < b,
+            Op::Greater => a > b,
+            Op::LessOrEqual => a <= b,
+            Op::GreaterOrEqual => a >= b,
+        };
+    }
+
+    match op {
+        Op::Equal => text == literal,
+        Op::NotEqual => text != literal,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_config_file;
+
+    const INPUT: &str = r#"
+        technologies = {
+            adm_tech_0 = { monarch_power = ADM cost = 100 allow = { tag = SWE } }
+            dip_tech_0 = { monarch_power = DIP cost = 200 allow = { tag = FIN } }
+            adm_tech_1 = { monarch_power = ADM cost = 300 }
+        }
+        "quoted key" = { tag = DAN }
+    "#;
+
+    fn select(query: &str) -> Vec<String> {
+        let pairs = parse_config_file(INPUT).unwrap();
+
+        Query::parse(query)
+            .unwrap()
+            .select(&pairs)
+            .iter()
+            .map(|found| format!("{} = {}", found.path.join("/"), found.pair.value))
+            .collect()
+    }
+
+    #[test]
+    fn test_query_paths() {
+        assert_eq!(
+            select("technologies/*/allow/tag"),
+            [
+                "technologies/adm_tech_0/allow/tag = SWE",
+                "technologies/dip_tech_0/allow/tag = FIN"
+            ]
+        );
+        assert_eq!(
+            select("**/tag"),
+            [
+                "technologies/adm_tech_0/allow/tag = SWE",
+                "technologies/dip_tech_0/allow/tag = FIN",
+                "\"quoted key\"/tag = DAN"
+            ]
+        );
+        assert_eq!(select("\"quoted key\"/tag"), ["\"quoted key\"/tag = DAN"]);
+    }
+
+    #[test]
+    fn test_query_predicates() {
+        assert_eq!(
+            select("*/*[monarch_power = ADM]/cost"),
+            [
+                "technologies/adm_tech_0/cost = 100",
+                "technologies/adm_tech_1/cost = 300"
+            ]
+        );
+        assert_eq!(
+            select("**/*[monarch_power = ADM][cost > 150]/cost"),
+            ["technologies/adm_tech_1/cost = 300"]
+        );
+        assert_eq!(
+            select("technologies/*[allow]/cost[. != 100]"),
+            ["technologies/dip_tech_0/cost = 200"]
+        );
+    }
+
+    #[test]
+    fn test_query_parse_error() {
+        assert!(Query::parse("technologies/[tag]").is_err());
+        assert!(Query::parse("a[b = ]").is_err());
+        assert!(Query::parse("").is_err());
+    }
+}
@@ -0,0 +1,173 @@
+//! Summary of how loading a game went, file by file.
+
+use std::cmp::Reverse;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::time::Duration;
+
+use crate::parser::{Diagnostic, Severity};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FileStatus {
+    Ok,
+    /// Parsed completely, but something looked wrong.
+    Warnings,
+    /// Only part of the file could be parsed.
+    Errors,
+    /// The file or folder could not be read at all.
+    Unreadable,
+}
+
+impl Display for FileStatus {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            FileStatus::Ok => write!(f, "ok"),
+            FileStatus::Warnings => write!(f, "warnings"),
+            FileStatus::Errors => write!(f, "errors"),
+            FileStatus::Unreadable => write!(f, "unreadable"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FileReport {
+    /// Path relative to the loaded folder.
+    pub path: String,
+    pub status: FileStatus,
+    /// Time spent reading and parsing the file.
+    pub parse_time: Duration,
+    /// Number of top level pairs.
+    pub pairs: usize,
+    pub diagnostics: Vec<Diagnostic>,
+    /// Why the file could not be read, for [`FileStatus::Unreadable`].
+    pub error: Option<String>,
+}
+
+impl FileReport {
+    pub fn new(
+        path: String,
+        parse_time: Duration,
+        pairs: usize,
+        diagnostics: Vec<Diagnostic>,
+    ) -> Self {
+        let status = if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+            FileStatus::Errors
+        } else if diagnostics.is_empty() {
+            FileStatus::Ok
+        } else {
+            FileStatus::Warnings
+        };
+
+        FileReport {
+            path,
+            status,
+            parse_time,
+            pairs,
+            diagnostics,
+            error: None,
+        }
+    }
+
+    pub fn unreadable(path: String, error: &io::Error) -> Self {
+        FileReport {
+            path,
+            status: FileStatus::Unreadable,
+            parse_time: Duration::ZERO,
+            pairs: 0,
+            diagnostics: Vec::new(),
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+/// One [`FileReport`] for every file that was found, sorted by path.
+#[derive(Debug, Clone, Default)]
+pub struct LoadReport {
+    pub files: Vec<FileReport>,
+}
+
+impl LoadReport {
+    pub fn new(mut files: Vec<FileReport>) -> Self {
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        LoadReport { files }
+    }
+
+    /// Files that could not be read or have errors or warnings, worst first.
+    pub fn problems(&self) -> Vec<&FileReport> {
+        let mut problems: Vec<&FileReport> = self
+            .files
+            .iter()
+            .filter(|file| file.status != FileStatus::Ok)
+            .collect();
+
+        problems.sort_by_key(|file| Reverse(file.status));
+        problems
+    }
+
+    pub fn count(&self, status: FileStatus) -> usize {
+        self.files
+            .iter()
+            .filter(|file| file.status == status)
+            .count()
+    }
+
+    pub fn parse_time(&self) -> Duration {
+        self.files.iter().map(|file| file.parse_time).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Span;
+
+    fn diagnostic(severity: Severity) -> Diagnostic {
+        Diagnostic {
+            severity,
+            message: String::new(),
+            span: Span::default(),
+        }
+    }
+
+    #[test]
+    fn test_load_report() {
+        let report = LoadReport::new(vec![
+            FileReport::unreadable(
+                "d.txt".to_owned(),
+                &io::Error::new(io::ErrorKind::PermissionDenied, "permission denied"),
+            ),
+            FileReport::new("c.txt".to_owned(), Duration::from_millis(1), 3, vec![]),
+            FileReport::new(
+                "b.txt".to_owned(),
+                Duration::from_millis(2),
+                1,
+                vec![diagnostic(Severity::Warning)],
+            ),
+            FileReport::new(
+                "a.txt".to_owned(),
+                Duration::from_millis(3),
+                0,
+                vec![diagnostic(Severity::Warning), diagnostic(Severity::Error)],
+            ),
+        ]);
+
+        let paths: Vec<&str> = report.files.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(paths, ["a.txt", "b.txt", "c.txt", "d.txt"]);
+
+        let problems: Vec<(&str, FileStatus)> = report
+            .problems()
+            .iter()
+            .map(|file| (file.path.as_str(), file.status))
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                ("d.txt", FileStatus::Unreadable),
+                ("a.txt", FileStatus::Errors),
+                ("b.txt", FileStatus::Warnings)
+            ]
+        );
+        assert_eq!(report.count(FileStatus::Ok), 1);
+        assert_eq!(report.parse_time(), Duration::from_millis(6));
+    }
+}
@@ -0,0 +1,306 @@
+//! Search over the keys and values of every loaded file.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+
+use rayon::prelude::*;
+use regex::{Regex, RegexBuilder};
+
+use crate::game::ParsedFile;
+use crate::parser::{ConfigPair, ConfigValue, Span};
+
+/// What part of each pair a query is matched against.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchTarget {
+    #[default]
+    Keys,
+    /// Scalar values, and arrays as they are written, such as `{ 1 2 3 }`.
+    Values,
+    /// A `key = value` query, where both sides have to match.
+    Pairs,
+}
+
+impl SearchTarget {
+    pub const ALL: [SearchTarget; 3] = [
+        SearchTarget::Keys,
+        SearchTarget::Values,
+        SearchTarget::Pairs,
+    ];
+}
+
+impl Display for SearchTarget {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            SearchTarget::Keys => write!(f, "Keys"),
+            SearchTarget::Values => write!(f, "Values"),
+            SearchTarget::Pairs => write!(f, "key = value"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatchMode {
+    #[default]
+    Substring,
+    Regex,
+    /// The query has to match whole words, so `tag` does not match `has_tag`.
+    WholeWord,
+}
+
+impl MatchMode {
+    pub const ALL: [MatchMode; 3] = [MatchMode::Substring, MatchMode::Regex, MatchMode::WholeWord];
+}
+
+impl Display for MatchMode {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            MatchMode::Substring => write!(f, "Substring"),
+            MatchMode::Regex => write!(f, "Regex"),
+            MatchMode::WholeWord => write!(f, "Whole word"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SearchOptions {
+    pub target: SearchTarget,
+    pub mode: MatchMode,
+    pub case_sensitive: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    /// Relative path of the file the pair is in.
+    pub file: String,
+    /// Keys leading to the pair, the pair's own key last.
+    pub path: Vec<String>,
+    pub value: String,
+    pub span: Span,
+}
+
+impl SearchResult {
+    /// The keys of [`path`](Self::path) joined with `/`.
+    pub fn path_string(&self) -> String {
+        self.path.join("/")
+    }
+}
+
+/// A compiled query.
+#[derive(Debug, Clone)]
+pub struct Matcher {
+    target: SearchTarget,
+    key: Option<Regex>,
+    value: Option<Regex>,
+}
+
+impl Matcher {
+    pub fn new(query: &str, options: SearchOptions) -> Result<Self, regex::Error> {
+        let compile = |pattern: &str| {
+            let pattern = match options.mode {
+                MatchMode::Substring => regex::escape(pattern),
+                MatchMode::Regex => pattern.to_owned(),
+                MatchMode::WholeWord => format!(r"\b{}\b", regex::escape(pattern)),
+            };
+
+            RegexBuilder::new(&pattern)
+                .case_insensitive(!options.case_sensitive)
+                .build()
+        };
+
+        let (key, value) = match options.target {
+            SearchTarget::Keys => (Some(compile(query)?), None),
+            SearchTarget::Values => (None, Some(compile(query)?)),
+            SearchTarget::Pairs => {
+                let (key, value) = query.split_once('=').unwrap_or((query, ""));
+                let key = key.trim();
+                let value = value.trim();
+
+                (
+                    (!key.is_empty()).then(|| compile(key)).transpose()?,
+                    (!value.is_empty()).then(|| compile(value)).transpose()?,
+                )
+            }
+        };
+
+        Ok(Matcher {
+            target: options.target,
+            key,
+            value,
+        })
+    }
+
+    pub fn is_match(&self, pair: &ConfigPair) -> bool {
+        let key_matches = || {
+            self.key
+                .as_ref()
+                .is_some_and(|key| key.is_match(&pair.identifier))
+        };
+        let value_matches = || {
+            // Strings are matched without their quotes, so `^Sweden$` finds `"Sweden"`.
+            let text = match &pair.value {
+                ConfigValue::Object(_) => return false,
+                ConfigValue::String(text) | ConfigValue::Identifier(text) => Cow::from(text),
+                value => Cow::from(value.to_string()),
+            };
+            self.value
+                .as_ref()
+                .is_some_and(|value| value.is_match(&text))
+        };
+
+        match self.target {
+            SearchTarget::Keys => key_matches(),
+            SearchTarget::Values => value_matches(),
+            SearchTarget::Pairs => {
+                (self.key.is_none() || key_matches())
+                    && (self.value.is_none() || value_matches())
+                    && (self.key.is_some() || self.value.is_some())
+            }
+        }
+    }
+}
+
+/// Searches every file, returning matches sorted by file and then position.
+pub fn search(
+    files: &HashMap<String, ParsedFile>,
+    query: &str,
+    options: SearchOptions,
+) -> Result<Vec<SearchResult>, regex::Error> {
+    let matcher = Matcher::new(query, options)?;
+
+    let mut results: Vec<SearchResult> = files
+        .par_iter()
+        .flat_map_iter(|(name, file)| search_pairs(name, &file.pairs, &matcher))
+        .collect();
+
+    results.sort_by(|a, b| a.file.cmp(&b.file).then(a.span.start.cmp(&b.span.start)));
+    Ok(results)
+}
+
+/// Searches the pairs of one file, named `file` in the results.
+pub fn search_pairs(file: &str, pairs: &[ConfigPair], matcher: &Matcher) -> Vec<SearchResult> {
+    fn walk(
+        file: &str,
+        pairs: &[ConfigPair],
+        matcher: &Matcher,
+        path: &mut Vec<String>,
+        results: &mut Vec<SearchResult>,
+    ) {
+        for pair in pairs {
+            path.push(pair.identifier.clone());
+
+            if matcher.is_match(pair) {
+                results.push(SearchResult {
+                    file: file.to_owned(),
+                    path: path.clone(),
+                    value: match pair.value {
+                        ConfigValue::Object(_) => String::new(),
+                        ref value => value.to_string(),
+                    },
+                    span: pair.span,
+                });
+            }
+
+            if let ConfigValue::Object(children) = &pair.value {
+                walk(file, children, matcher, path, results);
+            }
+
+            path.pop();
+        }
+    }
+
+    let mut results = Vec::new();
+    walk(file, pairs, matcher, &mut Vec::new(), &mut results);
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_config_file;
+
+    const INPUT: &str = r#"
+        country_event = {
+            trigger = { tag = SWE has_tag = yes }
+            option = { name = "Raise the Tag" }
+        }
+        tag = FIN
+    "#;
+
+    fn find(query: &str, target: SearchTarget, mode: MatchMode) -> Vec<String> {
+        let pairs = parse_config_file(INPUT).unwrap();
+        let options = SearchOptions {
+            target,
+            mode,
+            case_sensitive: false,
+        };
+
+        search_pairs("events.txt", &pairs, &Matcher::new(query, options).unwrap())
+            .iter()
+            .map(SearchResult::path_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_search_keys() {
+        assert_eq!(
+            find("tag", SearchTarget::Keys, MatchMode::Substring),
+            [
+                "country_event/trigger/tag",
+                "country_event/trigger/has_tag",
+                "tag"
+            ]
+        );
+        assert_eq!(
+            find("tag", SearchTarget::Keys, MatchMode::WholeWord),
+            ["country_event/trigger/tag", "tag"]
+        );
+        assert_eq!(
+            find("^(tag|name)$", SearchTarget::Keys, MatchMode::Regex),
+            [
+                "country_event/trigger/tag",
+                "country_event/option/name",
+                "tag"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_search_values() {
+        assert_eq!(
+            find("tag", SearchTarget::Values, MatchMode::WholeWord),
+            ["country_event/option/name"]
+        );
+        assert_eq!(
+            find("swe", SearchTarget::Values, MatchMode::Substring),
+            ["country_event/trigger/tag"]
+        );
+        assert_eq!(
+            find("^Raise the Tag$", SearchTarget::Values, MatchMode::Regex),
+            ["country_event/option/name"]
+        );
+        assert!(find("\"", SearchTarget::Values, MatchMode::Substring).is_empty());
+    }
+
+    #[test]
+    fn test_search_pairs() {
+        assert_eq!(
+            find("tag = FIN", SearchTarget::Pairs, MatchMode::WholeWord),
+            ["tag"]
+        );
+        assert_eq!(
+            find("= yes", SearchTarget::Pairs, MatchMode::WholeWord),
+            ["country_event/trigger/has_tag"]
+        );
+    }
+
+    #[test]
+    fn test_search_invalid_regex() {
+        let options = SearchOptions {
+            mode: MatchMode::Regex,
+            ..SearchOptions::default()
+        };
+
+        assert!(search(&HashMap::new(), "(", options).is_err());
+    }
+}
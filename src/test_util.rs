@@ -0,0 +1,42 @@
+//! Helpers shared by the tests.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// An empty directory for a test to build files in, removed again when dropped, also when
+/// the test fails.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Creates the directory. It is named after `name`, the process and a counter, so tests
+    /// running at the same time, in this run or another, never share one.
+    pub fn new(name: &str) -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "clausewitz-viewer-test-{}-{}-{}",
+            name,
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        // Left over from an earlier run with the same process id.
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+
+        TempDir { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
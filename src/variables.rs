@@ -0,0 +1,342 @@
+//! Scripted variables, declared with `@my_var = 10` and used as `@my_var` or in inline math
+//! such as `@[ my_var * 2 ]`.
+//!
+//! A file can use the variables it declares itself, after their declaration, and those
+//! declared in `common/scripted_variables`.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+
+use crate::game::ParsedFile;
+use crate::parser::{ConfigPair, ConfigValue};
+
+/// Folder of the files declaring variables every file can use.
+pub const SCRIPTED_VARIABLES: &str = "common/scripted_variables/";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VariableError {
+    Unknown(String),
+    /// A variable used in math whose value is not a number.
+    NotANumber(String),
+    DivisionByZero,
+    Syntax(String),
+}
+
+impl Display for VariableError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            VariableError::Unknown(name) => write!(f, "unknown variable `@{}`", name),
+            VariableError::NotANumber(name) => write!(f, "`@{}` is not a number", name),
+            VariableError::DivisionByZero => write!(f, "division by zero"),
+            VariableError::Syntax(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for VariableError {}
+
+/// Values of variables by name, without the `@`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Variables<'a> {
+    values: HashMap<String, ConfigValue>,
+    /// The variables these are declared on top of, for names these do not have.
+    outer: Option<&'a Variables<'a>>,
+}
+
+impl<'a> Variables<'a> {
+    /// No variables of their own, on top of `self`, so a file can declare its own without
+    /// copying those every file can use.
+    pub fn nested(&'a self) -> Self {
+        Variables {
+            values: HashMap::new(),
+            outer: Some(self),
+        }
+    }
+
+    /// Adds the variables declared at the top level of `pairs`, in order, so a declaration
+    /// can use the ones before it. A declaration replaces an earlier one of the same name.
+    /// Declarations that cannot be resolved are left out, so using them is an error too.
+    pub fn declare(&mut self, pairs: &[ConfigPair]) {
+        for pair in pairs {
+            let Some(name) = pair.identifier.strip_prefix('@') else {
+                continue;
+            };
+            if let Ok(value) = self.resolve(&pair.value) {
+                self.values.insert(name.to_owned(), value);
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ConfigValue> {
+        self.values
+            .get(name)
+            .or_else(|| self.outer.and_then(|outer| outer.get(name)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty() && self.outer.is_none_or(Variables::is_empty)
+    }
+
+    /// The value `value` stands for: the value of a variable, the result of inline math,
+    /// or `value` itself for anything else.
+    pub fn resolve(&self, value: &ConfigValue) -> Result<ConfigValue, VariableError> {
+        match value {
+            ConfigValue::Variable(name) => self
+                .get(name)
+                .cloned()
+                .ok_or_else(|| VariableError::Unknown(name.clone())),
+            ConfigValue::Math(expression) => self.evaluate(expression).map(ConfigValue::Number),
+            value => Ok(value.clone()),
+        }
+    }
+
+    /// Evaluates inline math such as `my_var * 2`, with `+`, `-`, `*`, `/` and parentheses.
+    /// Variables may be written with or without the `@`.
+    pub fn evaluate(&self, expression: &str) -> Result<f64, VariableError> {
+        let mut parser = Expression {
+            variables: self,
+            input: expression,
+            offset: 0,
+        };
+        let result = parser.sum()?;
+
+        match parser.peek() {
+            None => Ok(result),
+            Some(c) => Err(parser.unexpected(c)),
+        }
+    }
+}
+
+/// The variables declared in [`SCRIPTED_VARIABLES`], files in order of their names.
+pub fn scripted_variables(files: &HashMap<String, ParsedFile>) -> Variables<'static> {
+    let mut names: Vec<&String> = files
+        .keys()
+        .filter(|name| name.starts_with(SCRIPTED_VARIABLES))
+        .collect();
+    names.sort();
+
+    let mut variables = Variables::default();
+    for name in names {
+        variables.declare(&files[name].pairs);
+    }
+    variables
+}
+
+/// A recursive descent parser for inline math, which evaluates as it goes.
+struct Expression<'a> {
+    variables: &'a Variables<'a>,
+    input: &'a str,
+    offset: usize,
+}
+
+impl<'a> Expression<'a> {
+    fn sum(&mut self) -> Result<f64, VariableError> {
+        let mut result = self.product()?;
+
+        while let Some(op @ ('+' | '-')) = self.peek() {
+            self.offset += 1;
+            let operand = self.product()?;
+            result = if op == '+' {
+                result + operand
+            } else {
+                result - operand
+            };
+        }
+
+        Ok(result)
+    }
+
+    fn product(&mut self) -> Result<f64, VariableError> {
+        let mut result = self.factor()?;
+
+        while let Some(op @ ('*' | '/')) = self.peek() {
+            self.offset += 1;
+            let operand = self.factor()?;
+            result = if op == '*' {
+                result * operand
+            } else if operand == 0.0 {
+                return Err(VariableError::DivisionByZero);
+            } else {
+                result / operand
+            };
+        }
+
+        Ok(result)
+    }
+
+    fn factor(&mut self) -> Result<f64, VariableError> {
+        match self.peek() {
+            Some('-') => {
+                self.offset += 1;
+                Ok(-self.factor()?)
+            }
+            Some('(') => {
+                self.offset += 1;
+                let result = self.sum()?;
+                match self.peek() {
+                    Some(')') => {
+                        self.offset += 1;
+                        Ok(result)
+                    }
+                    Some(c) => Err(self.unexpected(c)),
+                    None => Err(VariableError::Syntax("expected `)`".to_owned())),
+                }
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let number = self.take(|c| c.is_ascii_digit() || c == '.');
+                number
+                    .parse()
+                    .map_err(|_| VariableError::Syntax(format!("`{}` is not a number", number)))
+            }
+            Some(c) if c.is_alphanumeric() || c == '_' || c == '@' => {
+                let name = self.take(|c| c.is_alphanumeric() || c == '_' || c == '@');
+                let name = name.trim_start_matches('@');
+                match self.variables.get(name) {
+                    Some(ConfigValue::Number(number)) => Ok(*number),
+                    Some(_) => Err(VariableError::NotANumber(name.to_owned())),
+                    None => Err(VariableError::Unknown(name.to_owned())),
+                }
+            }
+            Some(c) => Err(self.unexpected(c)),
+            None => Err(VariableError::Syntax(
+                "expected a number or a variable".to_owned(),
+            )),
+        }
+    }
+
+    /// The next character that is not whitespace, skipping the whitespace.
+    fn peek(&mut self) -> Option<char> {
+        let rest = &self.input[self.offset..];
+        self.offset += rest.len() - rest.trim_start().len();
+        self.input[self.offset..].chars().next()
+    }
+
+    fn take(&mut self, accept: impl Fn(char) -> bool) -> &'a str {
+        let start = self.offset;
+        let rest = &self.input[start..];
+        self.offset += rest.find(|c| !accept(c)).unwrap_or(rest.len());
+        &self.input[start..self.offset]
+    }
+
+    fn unexpected(&self, c: char) -> VariableError {
+        VariableError::Syntax(format!("unexpected `{}` at {}", c, self.offset + 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::Encoding;
+    use crate::parser::parse_config_file;
+
+    const INPUT: &str = "@base = 10\n@name = sweden\n@double = @[ base * 2 ]\n@alias = @double\nvalue = @[ (double - base) / -4 + 1 ]\n";
+
+    fn variables() -> Variables<'static> {
+        let mut variables = Variables::default();
+        variables.declare(&parse_config_file(INPUT).unwrap());
+        variables
+    }
+
+    #[test]
+    fn test_declare() {
+        let variables = variables();
+
+        assert_eq!(variables.get("base"), Some(&ConfigValue::Number(10.0)));
+        assert_eq!(variables.get("double"), Some(&ConfigValue::Number(20.0)));
+        assert_eq!(variables.get("alias"), Some(&ConfigValue::Number(20.0)));
+        assert_eq!(variables.get("value"), None);
+
+        let pairs = parse_config_file(INPUT).unwrap();
+        assert_eq!(
+            pairs[4].value,
+            ConfigValue::Math(" (double - base) / -4 + 1 ".to_owned())
+        );
+        assert_eq!(
+            variables.resolve(&pairs[4].value),
+            Ok(ConfigValue::Number(-1.5))
+        );
+    }
+
+    #[test]
+    fn test_evaluate_errors() {
+        let variables = variables();
+
+        assert_eq!(variables.evaluate("@base + 2 * 3"), Ok(16.0));
+        assert_eq!(
+            variables.evaluate("base + missing"),
+            Err(VariableError::Unknown("missing".to_owned()))
+        );
+        assert_eq!(
+            variables.evaluate("name * 2"),
+            Err(VariableError::NotANumber("name".to_owned()))
+        );
+        assert_eq!(
+            variables.evaluate("base / (2 - 2)"),
+            Err(VariableError::DivisionByZero)
+        );
+        assert!(matches!(
+            variables.evaluate("base +"),
+            Err(VariableError::Syntax(_))
+        ));
+        assert!(matches!(
+            variables.evaluate("(base"),
+            Err(VariableError::Syntax(_))
+        ));
+        assert!(matches!(
+            variables.evaluate("base 2"),
+            Err(VariableError::Syntax(_))
+        ));
+        assert_eq!(
+            variables.resolve(&ConfigValue::Variable("missing".to_owned())),
+            Err(VariableError::Unknown("missing".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_scripted_variables() {
+        let file = |text: &str| ParsedFile {
+            pairs: parse_config_file(text).unwrap(),
+            encoding: Encoding::Utf8,
+            diagnostics: Vec::new(),
+            layer: 0,
+        };
+        let files = HashMap::from([
+            (
+                "common/scripted_variables/00_base.txt".to_owned(),
+                file("@a = 1\n"),
+            ),
+            (
+                "common/scripted_variables/01_more.txt".to_owned(),
+                file("@b = @[ a + 1 ]\n"),
+            ),
+            ("events/a.txt".to_owned(), file("@c = 3\n")),
+        ]);
+
+        let mut variables = scripted_variables(&files);
+        assert_eq!(variables.get("b"), Some(&ConfigValue::Number(2.0)));
+        assert_eq!(variables.get("c"), None);
+
+        variables.declare(&files["events/a.txt"].pairs);
+        assert_eq!(variables.evaluate("b * c"), Ok(6.0));
+    }
+
+    #[test]
+    fn test_nested() {
+        let outer = variables();
+        let mut nested = outer.nested();
+        nested.declare(
+            &parse_config_file(
+                "@base = 1
+@triple = @[ base * 3 ]
+",
+            )
+            .unwrap(),
+        );
+
+        assert_eq!(nested.get("triple"), Some(&ConfigValue::Number(3.0)));
+        assert_eq!(nested.evaluate("base + double"), Ok(21.0));
+        assert_eq!(outer.get("base"), Some(&ConfigValue::Number(10.0)));
+        assert_eq!(outer.get("triple"), None);
+        assert!(Variables::default().nested().is_empty());
+    }
+}